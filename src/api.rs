@@ -0,0 +1,78 @@
+//! Direct access to the speedrun.com API for data that isn't exposed by `srcomapi`.
+
+use {
+    std::{
+        fs,
+        path::PathBuf,
+        time::{
+            Duration,
+            SystemTime
+        }
+    },
+    log::debug,
+    reqwest::header::{
+        HeaderMap,
+        HeaderValue,
+        USER_AGENT
+    },
+    serde_json::Value as Json,
+    crate::{
+        Error,
+        logging
+    }
+};
+
+const DEFAULT_API_URL: &str = "https://www.speedrun.com/api/v1";
+/// Cached responses older than this are downloaded again.
+const CACHE_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Clone)]
+pub(crate) struct RawClient {
+    http: reqwest::Client,
+    base_url: String,
+    cache_dir: Option<PathBuf>
+}
+
+impl RawClient {
+    pub(crate) fn new(api_url: Option<&str>) -> Result<RawClient, Error> {
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, HeaderValue::from_static(concat!("bitbar-speedruncom/", env!("CARGO_PKG_VERSION"))));
+        Ok(RawClient {
+            http: reqwest::Client::builder().default_headers(headers).build()?,
+            base_url: api_url.unwrap_or(DEFAULT_API_URL).to_owned(),
+            cache_dir: xdg_basedir::get_cache_home().ok().map(|cache| cache.join("bitbar/speedruncom-api"))
+        })
+    }
+
+    /// Returns the `data` field of the API response for the given path, e.g. `users/abc123`.
+    ///
+    /// Responses are cached on disk for a day.
+    pub(crate) fn get(&self, path: &str) -> Result<Json, Error> {
+        let cache_path = self.cache_dir.as_ref().map(|cache_dir| cache_dir.join(format!("{}.json", path.replace(|c: char| !c.is_ascii_alphanumeric(), "_"))));
+        if let Some(ref cache_path) = cache_path {
+            let fresh = fs::metadata(cache_path).and_then(|metadata| metadata.modified()).ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                .map_or(false, |age| age < CACHE_TIMEOUT);
+            if fresh {
+                if let Ok(data) = fs::read(cache_path).map_err(Error::Io).and_then(|buf| serde_json::from_slice(&buf).map_err(Error::SerDe)) {
+                    debug!("cache_hit kind=raw path={}", path);
+                    return Ok(data);
+                }
+            }
+        }
+        let mut response = logging::http_request("raw", path, || self.http.get(&format!("{}/{}", self.base_url, path)).send()?.error_for_status()?.json::<Json>())?;
+        let data = response["data"].take();
+        if let Some(cache_path) = cache_path {
+            fs::create_dir_all(cache_path.parent().expect("cache path has no parent"))?;
+            fs::write(cache_path, serde_json::to_vec(&data)?)?;
+        }
+        Ok(data)
+    }
+
+    /// Downloads the file at the given absolute URL.
+    pub(crate) fn download(&self, url: &str) -> Result<Vec<u8>, Error> {
+        let mut buf = Vec::default();
+        logging::http_request("download", url, || self.http.get(url).send()?.error_for_status()?.copy_to(&mut buf))?;
+        Ok(buf)
+    }
+}
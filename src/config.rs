@@ -1,24 +1,109 @@
 use {
     std::{
+        cmp::Ordering,
         collections::{
             BTreeMap,
             BTreeSet
         },
-        fs::File
+        env,
+        fmt::Write as _,
+        fs::File,
+        io::prelude::*,
+        path::{
+            Path,
+            PathBuf
+        },
+        time::Duration
     },
+    chrono::prelude::*,
     serde::{
         Deserialize,
-        Serialize
+        Serialize,
+        de::DeserializeOwned
     },
-    crate::Error
+    url::Url,
+    crate::{
+        Error,
+        IINA_PATH,
+        util::{
+            Clock,
+            DurationStyle
+        }
+    }
 };
 
+/// The file formats in which the config can be written, in order of precedence.
+pub(crate) const FORMATS: [Format; 3] = [Format::Toml, Format::Yaml, Format::Json];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    Json,
+    Toml,
+    Yaml
+}
+
+impl Format {
+    pub(crate) fn from_path(path: &Path) -> Result<Format, Error> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Ok(Format::Json),
+            Some("toml") => Ok(Format::Toml),
+            Some("yaml") | Some("yml") => Ok(Format::Yaml),
+            _ => Err(Error::UnknownConfigFormat(path.to_owned()))
+        }
+    }
+
+    /// The file name extensions recognized by `from_path`, in order of precedence.
+    fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            Format::Json => &["json"],
+            Format::Toml => &["toml"],
+            Format::Yaml => &["yaml", "yml"]
+        }
+    }
+
+    fn read<T: DeserializeOwned>(&self, mut file: File) -> Result<T, Error> {
+        Ok(match self {
+            Format::Json => serde_json::from_reader(file)?,
+            Format::Toml => {
+                let mut buf = String::default();
+                file.read_to_string(&mut buf)?;
+                toml::from_str(&buf)?
+            }
+            Format::Yaml => serde_yaml::from_reader(file)?
+        })
+    }
+
+    fn write<T: Serialize>(&self, value: &T, mut file: File) -> Result<(), Error> {
+        match self {
+            Format::Json => serde_json::to_writer_pretty(file, value)?,
+            // going through toml::Value makes sure tables are written after plain values
+            Format::Toml => write!(file, "{}", toml::to_string_pretty(&toml::Value::try_from(value)?)?)?,
+            Format::Yaml => serde_yaml::to_writer(file, value)?
+        }
+        Ok(())
+    }
+}
+
+fn make_true() -> bool { true }
+
+fn is_true(value: &bool) -> bool { *value }
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool { *value == T::default() }
+
+fn default_fill_minutes() -> Vec<u64> { vec![30, 60] }
+
+fn is_default_fill_minutes(fill_minutes: &[u64]) -> bool { fill_minutes == default_fill_minutes().as_slice() }
+
 #[derive(Debug, Default, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "camelCase", default)]
 pub(crate) struct ConfigCategory {
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
     pub(crate) src_categories: BTreeSet<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
     pub(crate) variable_state: BTreeMap<String, BTreeSet<String>>,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
     pub(crate) subcategories: BTreeSet<String>,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
     pub(crate) levels: BTreeSet<String> //TODO read in model
 }
 
@@ -26,42 +111,287 @@ pub(crate) struct ConfigCategory {
 #[serde(rename_all = "camelCase", default)]
 pub(crate) struct ConfigGame {
     /// maps SRC game IDs to their ignored categories
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
     pub(crate) src_games: BTreeMap<String, Vec<String>>,
-    pub(crate) categories: BTreeMap<String, ConfigCategory>
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) categories: BTreeMap<String, ConfigCategory>,
+    /// games with higher priority are listed first if `sortBy` is `priority`
+    #[serde(skip_serializing_if = "is_default")]
+    pub(crate) priority: i32
+}
+
+/// How to open a video.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(from = "String", into = "String")]
+pub(crate) enum Handler {
+    /// the configured `player`, written as `player`
+    Player,
+    /// the default browser, written as `browser`, or the macOS application with the given name, written as `browser:<name>`
+    Browser(Option<String>),
+    /// any other program, which is called with the video URL or path as its only argument
+    Program(PathBuf)
+}
+
+impl From<String> for Handler {
+    fn from(s: String) -> Handler {
+        match &s[..] {
+            "player" => Handler::Player,
+            "browser" => Handler::Browser(None),
+            _ => if let Some(app) = s.strip_prefix("browser:") {
+                Handler::Browser(Some(app.to_owned()))
+            } else {
+                Handler::Program(PathBuf::from(s))
+            }
+        }
+    }
+}
+
+impl From<Handler> for String {
+    fn from(handler: Handler) -> String {
+        match handler {
+            Handler::Player => "player".into(),
+            Handler::Browser(None) => "browser".into(),
+            Handler::Browser(Some(app)) => format!("browser:{}", app),
+            Handler::Program(path) => path.display().to_string()
+        }
+    }
+}
+
+/// How to check whether the videos of pending runs are still available.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum LinkCheck {
+    Off,
+    /// send a HEAD request to each video link
+    Head,
+    /// run the configured downloader with `--simulate`
+    YtDlp
+}
+
+impl Default for LinkCheck {
+    fn default() -> LinkCheck {
+        LinkCheck::Off
+    }
+}
+
+/// How game sections, and categories within them, are ordered in the menu.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum SortBy {
+    /// shortest pending WR first
+    Shortest,
+    /// most recently recorded pending WR first
+    Newest,
+    /// alphabetically
+    Name,
+    /// highest priority first, then shortest pending WR first
+    Priority
+}
+
+impl Default for SortBy {
+    fn default() -> SortBy {
+        SortBy::Shortest
+    }
+}
+
+/// The properties of a game section or category that it can be sorted by.
+pub(crate) struct SortKey {
+    pub(crate) name: String,
+    pub(crate) priority: i32,
+    pub(crate) fastest_time: Option<Duration>,
+    pub(crate) newest_date: Option<NaiveDate>
+}
+
+impl SortBy {
+    pub(crate) fn compare(&self, a: &SortKey, b: &SortKey) -> Ordering {
+        match self {
+            SortBy::Shortest => a.fastest_time.cmp(&b.fastest_time),
+            SortBy::Newest => b.newest_date.cmp(&a.newest_date).then_with(|| a.fastest_time.cmp(&b.fastest_time)),
+            SortBy::Name => a.name.cmp(&b.name),
+            SortBy::Priority => b.priority.cmp(&a.priority).then_with(|| a.fastest_time.cmp(&b.fastest_time))
+        }
+    }
+}
+
+/// A daily time range in local time, written as `HH:MM`, during which the count in the menu bar is hidden and notifications are suppressed.
+///
+/// If the end is before the start, the range spans midnight.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub(crate) struct QuietHours {
+    pub(crate) start: String,
+    pub(crate) end: String
+}
+
+impl QuietHours {
+    pub(crate) fn contains(&self, time: NaiveTime) -> Result<bool, Error> {
+        let start = NaiveTime::parse_from_str(&self.start, "%H:%M")?;
+        let end = NaiveTime::parse_from_str(&self.end, "%H:%M")?;
+        Ok(if start <= end {
+            start <= time && time < end
+        } else {
+            start <= time || time < end
+        })
+    }
 }
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase", default)]
 pub(crate) struct Config {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) api_key: Option<String>,
-    pub(crate) games: BTreeMap<String, ConfigGame>
+    /// the base URL of the speedrun.com API, e.g. to use a mock server for testing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) api_url: Option<String>,
+    /// whether to show each game's cover art next to its name
+    #[serde(default = "make_true", skip_serializing_if = "is_true")]
+    pub(crate) cover_art: bool,
+    /// `strftime`-style format for absolute dates, shown when holding option
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) date_format: Option<String>,
+    /// number of days after being watched at which downloaded videos are deleted, or never if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) delete_watched_downloads_after: Option<u64>,
+    /// program used to download videos for offline viewing, accepting the same arguments as yt-dlp
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) downloader: Option<PathBuf>,
+    #[serde(skip_serializing_if = "is_default")]
+    pub(crate) duration_style: DurationStyle,
+    /// time budgets, in minutes, for which to suggest a set of pending runs to watch
+    #[serde(default = "default_fill_minutes", skip_serializing_if = "is_default_fill_minutes")]
+    pub(crate) fill_minutes: Vec<u64>,
+    pub(crate) games: BTreeMap<String, ConfigGame>,
+    /// maps URL hosts, which also match their subdomains, to how videos hosted there are opened, with `file` for downloaded videos
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) handlers: BTreeMap<String, Handler>,
+    /// paths or glob patterns, relative to the config file, of files that each map game names to additional `ConfigGame`s
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) include: Vec<String>,
+    /// directory where videos downloaded for offline viewing are stored
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) library: Option<PathBuf>,
+    #[serde(skip_serializing_if = "is_default")]
+    pub(crate) link_check: LinkCheck,
+    /// the maximum number of runs listed in the main menu, the rest are moved into a “More…” submenu
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) max_items: Option<usize>,
+    /// path to IINA's command-line tool, or another program accepting the same arguments
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) player: Option<PathBuf>,
+    /// how often the daemon reloads runs from the API, in minutes (default: 5)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) poll_interval: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) quiet_hours: Option<QuietHours>,
+    #[serde(skip_serializing_if = "is_default")]
+    pub(crate) sort_by: SortBy,
+    /// whether to remove trailing zeros from fractional seconds in durations
+    #[serde(skip_serializing_if = "is_default")]
+    pub(crate) trim_zero_millis: bool
 }
 
 impl Config {
-    pub(crate) fn new() -> Result<Config, Error> {
-        let dirs = xdg_basedir::get_config_home().into_iter().chain(xdg_basedir::get_config_dirs());
-        let file = dirs.filter_map(|cfg_dir| File::open(cfg_dir.join("bitbar/plugins/speedruncom.json")).ok())
-            .next().ok_or(Error::MissingConfig)?;
-        Ok(serde_json::from_reader(file)?)
+    pub(crate) fn new(path: Option<&Path>) -> Result<Config, Error> {
+        Config::load(&Config::path(path)?)
     }
 
-    /*
-    pub(crate) fn save(self) -> Result<(), Error> {
+    /// Returns the path of the config file that should be used.
+    ///
+    /// An explicitly specified path is always used. Otherwise, config directories are searched in XDG order. Within a directory, TOML takes precedence over YAML (`.yaml`, then `.yml`), which takes precedence over JSON.
+    pub(crate) fn path(path: Option<&Path>) -> Result<PathBuf, Error> {
+        if let Some(path) = path { return Ok(path.to_owned()); }
         let dirs = xdg_basedir::get_config_home().into_iter().chain(xdg_basedir::get_config_dirs());
-        for cfg_dir in dirs {
-            let cfg_path = cfg_dir.join("bitbar/plugins/speedruncom.json");
-            if cfg_path.exists() {
-                if let Some(()) = File::create(cfg_path).ok()
-                    .and_then(|cfg_file| serde_json::to_writer_pretty(cfg_file, &self).ok())
-                {
-                    return Ok(());
+        dirs.flat_map(|cfg_dir| FORMATS.iter().flat_map(|format| format.extensions()).map(move |ext| cfg_dir.join(format!("bitbar/plugins/speedruncom.{}", ext))))
+            .find(|cfg_path| cfg_path.exists())
+            .ok_or(Error::MissingConfig)
+    }
+
+    /// Loads the config file at the given path and merges the games from its included files into it.
+    pub(crate) fn load(path: &Path) -> Result<Config, Error> {
+        let mut config = Config::load_without_includes(path)?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+        for pattern in &config.include {
+            let pattern = base_dir.join(pattern);
+            let pattern = pattern.to_str().ok_or_else(|| Error::InvalidIncludePath(pattern.clone()))?;
+            let include_paths = if glob::Pattern::escape(pattern) == pattern {
+                vec![PathBuf::from(pattern)] // not a glob pattern, so the file should exist
+            } else {
+                glob::glob(pattern)?.collect::<Result<Vec<_>, _>>()?
+            };
+            for include_path in include_paths {
+                let games = Format::from_path(&include_path)?.read::<BTreeMap<String, ConfigGame>>(File::open(&include_path)?)?;
+                for (game_name, game) in games {
+                    if config.games.contains_key(&game_name) {
+                        return Err(Error::ConflictingGame { game_name, path: include_path });
+                    }
+                    config.games.insert(game_name, game);
                 }
             }
         }
-        let cfg_path = xdg_basedir::get_config_home()?.join("bitbar/plugins/speedruncom.json");
-        let cfg_file = File::create(cfg_path)?;
-        serde_json::to_writer_pretty(cfg_file, &self)?;
-        Ok(())
+        if let Some(ref date_format) = config.date_format {
+            // chrono only reports an invalid format when formatting, and format! panics on that, so try it out on a sample date
+            let sample_date = "2000-01-01".parse::<NaiveDate>().expect("failed to parse sample date");
+            if write!(String::default(), "{}", sample_date.format(date_format)).is_err() {
+                return Err(Error::InvalidDateFormat(date_format.clone()));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Loads the config file at the given path, ignoring the `include` list.
+    pub(crate) fn load_without_includes(path: &Path) -> Result<Config, Error> {
+        Format::from_path(path)?.read(File::open(path)?)
+    }
+
+    /// The base URL of the speedrun.com API, if it's overridden by the `BITBAR_SPEEDRUNCOM_API_URL` environment variable or the config.
+    pub(crate) fn api_url(&self) -> Option<String> {
+        env::var("BITBAR_SPEEDRUNCOM_API_URL").ok().or_else(|| self.api_url.clone())
+    }
+
+    pub(crate) fn date_format(&self) -> &str {
+        self.date_format.as_deref().unwrap_or("%Y-%m-%d")
+    }
+
+    pub(crate) fn is_quiet_hour(&self, clock: Clock) -> Result<bool, Error> {
+        self.quiet_hours.as_ref().map_or(Ok(false), |quiet_hours| quiet_hours.contains(clock.now().with_timezone(&Local).time()))
+    }
+
+    pub(crate) fn downloader(&self) -> &Path {
+        self.downloader.as_deref().unwrap_or_else(|| Path::new("yt-dlp"))
+    }
+
+    /// Returns how the given video, a URL or the path of a downloaded file, should be opened.
+    ///
+    /// Videos on hosts without a configured handler are opened in the player, or in the browser if the player isn't installed.
+    pub(crate) fn handler(&self, video: &str) -> Handler {
+        let host = match Url::parse(video) {
+            Ok(ref url) if url.scheme() != "file" => url.host_str().map(str::to_owned),
+            _ => Some("file".to_owned())
+        };
+        let handler = host.and_then(|host| self.handlers.iter()
+            .filter(|(pattern, _)| host == **pattern || host.ends_with(&format!(".{}", pattern)))
+            .max_by_key(|(pattern, _)| pattern.len()) // the most specific match wins
+            .map(|(_, handler)| handler.clone())
+        ).unwrap_or(Handler::Player);
+        if handler == Handler::Player && !self.player().exists() {
+            Handler::Browser(None)
+        } else {
+            handler
+        }
+    }
+
+    pub(crate) fn library(&self) -> Result<PathBuf, Error> {
+        Ok(if let Some(ref library) = self.library {
+            library.clone()
+        } else {
+            xdg_basedir::get_data_home()?.join("bitbar/speedruncom-library")
+        })
+    }
+
+    pub(crate) fn player(&self) -> &Path {
+        self.player.as_deref().unwrap_or_else(|| Path::new(IINA_PATH))
+    }
+
+    pub(crate) fn save_as(&self, path: &Path) -> Result<(), Error> {
+        Format::from_path(path)?.write(self, File::create(path)?)
     }
-    */
 }
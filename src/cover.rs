@@ -0,0 +1,38 @@
+//! Game cover art for the game headers in the menu, downloaded from speedrun.com once and cached as small PNGs.
+
+use {
+    std::{
+        fs,
+        io::Cursor
+    },
+    image::ImageOutputFormat,
+    log::debug,
+    crate::{
+        Error,
+        api::RawClient
+    }
+};
+
+/// Width and height of the cached images in pixels. This is twice the menu's font size for Retina displays.
+const SIZE: u32 = 32;
+
+/// Returns PNG data for the cover art of the given SRC game, or `None` if it has none.
+pub(crate) fn get(raw_client: &RawClient, game_id: &str) -> Result<Option<Vec<u8>>, Error> {
+    let cover_dir = xdg_basedir::get_cache_home()?.join("bitbar/speedruncom-covers");
+    let cover_path = cover_dir.join(format!("{}.png", game_id));
+    if cover_path.exists() {
+        debug!("cache_hit kind=cover id={}", game_id);
+        return Ok(Some(fs::read(cover_path)?));
+    }
+    let game = raw_client.get(&format!("games/{}", game_id))?;
+    let assets = &game["assets"];
+    let image_url = match ["cover-tiny", "icon"].iter().filter_map(|asset| assets[asset]["uri"].as_str()).next() {
+        Some(image_url) => image_url,
+        None => return Ok(None)
+    };
+    let mut png = Vec::default();
+    image::load_from_memory(&raw_client.download(image_url)?)?.thumbnail(SIZE, SIZE).write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+    fs::create_dir_all(&cover_dir)?;
+    fs::write(cover_path, &png)?;
+    Ok(Some(png))
+}
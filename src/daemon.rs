@@ -0,0 +1,253 @@
+//! A long-running process which keeps API responses in memory and answers plugin invocations over a Unix socket.
+
+use {
+    std::{
+        cell::RefCell,
+        fs,
+        io::{
+            self,
+            BufReader,
+            prelude::*
+        },
+        os::unix::net::{
+            UnixListener,
+            UnixStream
+        },
+        panic,
+        path::PathBuf,
+        rc::Rc,
+        sync::{
+            Arc,
+            Mutex,
+            atomic::{
+                AtomicBool,
+                Ordering::SeqCst
+            },
+            mpsc::{
+                self,
+                RecvTimeoutError
+            }
+        },
+        thread,
+        time::{
+            Duration,
+            Instant
+        }
+    },
+    log::{
+        info,
+        warn
+    },
+    serde::{
+        Deserialize,
+        Serialize
+    },
+    srcomapi::model::notification::Notification,
+    crate::{
+        Error,
+        Options,
+        api::RawClient,
+        model::Cache,
+        util::Clock
+    }
+};
+
+/// Subcommands which are handled by the daemon if one is running, so that they don't race each other when writing the data file.
+pub(crate) const SUBCOMMANDS: [&str; 6] = ["check", "defer", "focus", "snooze-category", "snooze-game", "unwatchable"];
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Request {
+    config_path: Option<PathBuf>,
+    data_path: Option<PathBuf>,
+    /// a subcommand and its arguments, or empty to render the menu
+    args: Vec<String>
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+enum Response {
+    Output(String),
+    Error(String),
+    /// the daemon was started with a different config or data file
+    WrongProfile
+}
+
+/// What the daemon keeps in memory between polls.
+struct State {
+    raw_client: RawClient,
+    cache: Rc<RefCell<Cache>>,
+    notifications: Vec<Notification>
+}
+
+impl State {
+    /// Creates a client with an empty cache, then renders the menu once to fill the cache.
+    fn poll(options: &Options) -> Result<State, Error> {
+        let config = options.config()?;
+        let (client, notifications) = crate::get_client(&config)?;
+        let raw_client = RawClient::new(config.api_url().as_deref())?;
+        let cache = Cache::new(&client, &raw_client);
+        if let Err(e) = crate::spawn_link_check(options, &config, &cache) {
+            warn!("spawn subcommand=check-links error={:?}", e.to_string());
+        }
+        if let Err(e) = crate::cleanup_downloads(options, &config) {
+            warn!("cleanup_downloads error={:?}", e.to_string());
+        }
+        crate::render(options, config, &cache, &raw_client, &notifications)?;
+        Ok(State { raw_client, cache, notifications })
+    }
+
+    /// Renders the menu using the cached API responses, or an error menu.
+    fn render(&self, options: &Options) -> String {
+        let menu = options.config().and_then(|config| crate::render(options, config, &self.cache, &self.raw_client, &self.notifications));
+        menu.unwrap_or_else(|e| crate::error_menu(options, e)).to_string()
+    }
+}
+
+/// The part of the daemon which serves connections. It runs on its own thread so that polling the API doesn't keep requests waiting.
+struct Server {
+    options: Options,
+    /// Requests to render the menu, sent to the main thread along with where to send the menu.
+    render_requests: mpsc::Sender<mpsc::Sender<String>>,
+    /// Set while the main thread is polling the API and can't render the menu.
+    polling: Arc<AtomicBool>,
+    /// The most recently rendered menu, used while polling.
+    last_menu: Arc<Mutex<String>>
+}
+
+impl Server {
+    fn handle(&self, args: Vec<String>) -> Result<String, Error> {
+        let options = &self.options;
+        let mut args = args.into_iter();
+        let subcmd = if let Some(subcmd) = args.next() {
+            subcmd
+        } else {
+            if !self.polling.load(SeqCst) {
+                let (tx, rx) = mpsc::channel();
+                if self.render_requests.send(tx).is_ok() {
+                    if let Ok(menu) = rx.recv() { return Ok(menu); }
+                }
+            }
+            return Ok(self.last_menu.lock().map(|menu| menu.clone()).unwrap_or_default());
+        };
+        match &subcmd[..] {
+            "check" => crate::check(options, args)?,
+            "defer" => crate::defer(options, args)?,
+            "focus" => crate::focus(options, args)?,
+            "snooze-category" => crate::snooze_category(options, args)?,
+            "snooze-game" => crate::snooze_game(options, args)?,
+            "unwatchable" => crate::unwatchable(options, args)?,
+            _ => { return Err(Error::UnknownSubcommand(subcmd)); }
+        }
+        Ok(String::default())
+    }
+}
+
+fn socket_path() -> Result<PathBuf, Error> {
+    Ok(xdg_basedir::get_cache_home()?.join("bitbar/speedruncom.sock"))
+}
+
+/// Asks a running daemon to render the menu (if `args` is empty) or run a subcommand.
+///
+/// Returns `None` if the daemon can't handle the request, e.g. because none is running, so the caller should handle it itself.
+pub(crate) fn request(options: &Options, args: Vec<String>) -> Option<Result<String, String>> {
+    if let Clock::Fixed(_) = options.clock { return None; } // the daemon always uses the current time
+    let stream = UnixStream::connect(socket_path().ok()?).ok()?;
+    stream.set_read_timeout(Some(Duration::from_secs(60))).ok()?;
+    let request = Request {
+        config_path: options.config_path.clone(),
+        data_path: options.data_path.clone(),
+        args
+    };
+    serde_json::to_writer(&stream, &request).ok()?;
+    (&stream).write_all(b"\n").ok()?;
+    let mut line = String::default();
+    BufReader::new(&stream).read_line(&mut line).ok()?;
+    match serde_json::from_str(&line).ok()? {
+        Response::Output(output) => Some(Ok(output)),
+        Response::Error(e) => Some(Err(e)),
+        Response::WrongProfile => None
+    }
+}
+
+fn serve_connection(server: &Server, stream: UnixStream) -> Result<(), Error> {
+    let options = &server.options;
+    stream.set_nonblocking(false)?;
+    // don't let a client which never finishes its request block the daemon
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut line = String::default();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let request = serde_json::from_str::<Request>(&line)?;
+    let response = if request.config_path != options.config_path || request.data_path != options.data_path {
+        Response::WrongProfile
+    } else {
+        info!("daemon_request args={:?}", request.args);
+        match server.handle(request.args) {
+            Ok(output) => Response::Output(output),
+            Err(e) => Response::Error(e.to_string())
+        }
+    };
+    serde_json::to_writer(&stream, &response)?;
+    (&stream).write_all(b"\n")?;
+    Ok(())
+}
+
+/// Accepts connections until accepting fails.
+fn accept_loop(server: Server, listener: UnixListener) -> io::Result<()> {
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => if let Err(e) = serve_connection(&server, stream) {
+                warn!("daemon_request error={:?}", e.to_string());
+            },
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => { thread::sleep(Duration::from_millis(100)); }
+            Err(e) => { return Err(e); }
+        }
+    }
+}
+
+/// Runs the daemon until the process is killed.
+///
+/// The API responses are kept on this thread, which renders the menu when asked and polls the API in between.
+pub(crate) fn run(options: &Options) -> Result<(), Error> {
+    let path = socket_path()?;
+    if UnixStream::connect(&path).is_ok() { return Err(Error::DaemonRunning); }
+    let poll_interval = Duration::from_secs(60 * options.config()?.poll_interval.unwrap_or(5));
+    let mut state = State::poll(options)?;
+    let mut last_poll = Instant::now();
+    let polling = Arc::new(AtomicBool::new(false));
+    let last_menu = Arc::new(Mutex::new(state.render(options)));
+    if path.exists() { fs::remove_file(&path)?; } // left behind by a daemon that didn't exit cleanly
+    if let Some(parent) = path.parent() { fs::create_dir_all(parent)?; }
+    let listener = UnixListener::bind(&path)?;
+    listener.set_nonblocking(true)?;
+    info!("daemon socket={}", path.display());
+    let (render_requests, renders) = mpsc::channel::<mpsc::Sender<String>>();
+    let server = Server {
+        options: options.clone(),
+        render_requests,
+        polling: Arc::clone(&polling),
+        last_menu: Arc::clone(&last_menu)
+    };
+    let acceptor = thread::spawn(move || accept_loop(server, listener));
+    loop {
+        match renders.recv_timeout(poll_interval.checked_sub(last_poll.elapsed()).unwrap_or_default()) {
+            Ok(reply) => {
+                let menu = state.render(options);
+                if let Ok(mut last_menu) = last_menu.lock() { *last_menu = menu.clone(); }
+                let _ = reply.send(menu);
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                polling.store(true, SeqCst);
+                match State::poll(options) {
+                    Ok(new_state) => { state = new_state; }
+                    Err(e) => { warn!("daemon_poll error={:?}", e.to_string()); } // keep showing the last known state
+                }
+                if let Ok(mut last_menu) = last_menu.lock() { *last_menu = state.render(options); }
+                polling.store(false, SeqCst);
+                last_poll = Instant::now();
+            }
+            // the accept loop only stops if accepting fails
+            Err(RecvTimeoutError::Disconnected) => { return Ok(acceptor.join().unwrap_or_else(|e| panic::resume_unwind(e))?); }
+        }
+    }
+}
@@ -1,46 +1,213 @@
 use {
     std::{
         collections::HashMap,
-        fs::File
+        fs::{
+            self,
+            File
+        },
+        io,
+        path::{
+            Path,
+            PathBuf
+        },
+        time::Duration as StdDuration
     },
-    chrono::prelude::*,
+    chrono::{
+        Duration,
+        prelude::*
+    },
+    log::info,
     serde::{
         Deserialize,
         Serialize
     },
-    crate::Error
+    crate::{
+        Error,
+        util::{
+            Clock,
+            LockFile
+        }
+    }
 };
 
+/// The path of a lock file for the data file at the given path, or at the default path if none is given.
+fn lock_path(path: Option<&Path>, suffix: &str) -> Result<PathBuf, Error> {
+    let data_path = match path {
+        Some(path) => path.to_owned(),
+        None => xdg_basedir::get_data_home()?.join("bitbar/plugin-cache/srcomapi.json")
+    };
+    let mut file_name = data_path.file_name().map(|file_name| file_name.to_owned()).unwrap_or_default();
+    file_name.push(format!(".{}", suffix));
+    Ok(data_path.with_file_name(file_name))
+}
+
+/// The result of checking whether a run's videos are still available.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct VideoCheck {
+    pub(crate) checked_at: DateTime<Utc>,
+    /// whether at least one of the run's videos is available, or `None` if none was found available and at least one couldn't be checked
+    pub(crate) available: Option<bool>
+}
+
 #[derive(Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase", default)]
 pub(crate) struct RunData {
     #[serde(default)]
     pub(crate) deferred: Option<DateTime<Utc>>,
+    /// paths of the run's videos downloaded for offline viewing
+    pub(crate) downloads: Vec<PathBuf>,
     #[serde(default)]
     pub(crate) unwatchable: bool,
-    pub(crate) watched: bool
+    pub(crate) video_check: Option<VideoCheck>,
+    pub(crate) watched: bool,
+    pub(crate) watched_at: Option<DateTime<Utc>>
+}
+
+impl RunData {
+    pub(crate) fn is_deferred(&self, clock: Clock) -> bool {
+        self.deferred.map_or(false, |deferred_until| deferred_until > clock.now())
+    }
+
+    /// The downloaded videos of this run, if it has been downloaded and none of the files have been removed since.
+    pub(crate) fn local_videos(&self) -> Option<&[PathBuf]> {
+        Some(&self.downloads[..]).filter(|downloads| !downloads.is_empty() && downloads.iter().all(|path| path.exists()))
+    }
+
+    pub(crate) fn is_video_unavailable(&self) -> bool {
+        self.video_check.map_or(false, |check| check.available == Some(false))
+    }
+
+    /// Whether the run's videos haven't been checked for availability in the last day, or in the last hour if that check was inconclusive.
+    pub(crate) fn needs_video_check(&self, clock: Clock) -> bool {
+        self.video_check.map_or(true, |check| check.checked_at + if check.available.is_some() { Duration::days(1) } else { Duration::hours(1) } <= clock.now())
+    }
+
+    /// Whether the run's downloads should be deleted because it was watched, or marked as unwatchable, at least the given number of days ago.
+    fn downloads_expired(&self, days: u64, clock: Clock) -> bool {
+        if self.downloads.is_empty() { return false; }
+        let since = match self.watched_at {
+            Some(watched_at) => watched_at,
+            // runs watched before watch times were recorded, and runs marked as unwatchable, count from when they were downloaded
+            None if self.watched || self.unwatchable => match self.downloads.iter().filter_map(|path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok()).max() {
+                Some(downloaded_at) => DateTime::from(downloaded_at),
+                None => return true // the files are already gone
+            },
+            None => return false
+        };
+        since + Duration::days(days as i64) <= clock.now()
+    }
+
+    pub(crate) fn mark_watched(&mut self, clock: Clock) {
+        self.watched = true;
+        self.watched_at = Some(clock.now());
+    }
+}
+
+/// Something the user can do with a pending run, from the menu or elsewhere.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Action {
+    Check,
+    Defer(DateTime<Utc>),
+    Unwatchable
 }
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase", default)]
 pub(crate) struct Data {
-    pub(crate) runs: HashMap<String, RunData>
+    /// the file this was loaded from if it was specified explicitly, so it's saved back there
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    /// focus mode hides the count in the menu bar and suppresses notifications until this time
+    pub(crate) focus_until: Option<DateTime<Utc>>,
+    pub(crate) runs: HashMap<String, RunData>,
+    /// maps game names to the time until which they're hidden
+    pub(crate) snoozed_games: HashMap<String, DateTime<Utc>>,
+    /// maps game names and category names to the time until which they're hidden
+    pub(crate) snoozed_categories: HashMap<String, HashMap<String, DateTime<Utc>>>
 }
 
 impl Data {
-    pub(crate) fn new() -> Result<Data, Error> {
+    /// Loads the data from the given path, or from the XDG data directories if no path is specified.
+    ///
+    /// A missing data file is treated as empty.
+    pub(crate) fn new(path: Option<&Path>) -> Result<Data, Error> {
+        if let Some(path) = path {
+            let mut data = if path.exists() { serde_json::from_reader(File::open(path)?)? } else { Data::default() };
+            data.path = Some(path.to_owned());
+            return Ok(data);
+        }
         let dirs = xdg_basedir::get_data_home().into_iter().chain(xdg_basedir::get_data_dirs());
         Ok(dirs.filter_map(|data_dir| File::open(data_dir.join("bitbar/plugin-cache/srcomapi.json")).ok())
             .next().map_or(Ok(Data::default()), serde_json::from_reader)?)
     }
 
-    pub(crate) fn save(self) -> Result<(), Error> {
+    /// Waits until no other process is changing the data file at the given path, or the default data file if none is given.
+    ///
+    /// Whoever makes a change should hold the lock from loading the data until saving it, so changes made by other processes at the same time aren't lost.
+    pub(crate) fn lock(path: Option<&Path>) -> Result<LockFile, Error> {
+        LockFile::acquire(lock_path(path, "lock")?, StdDuration::from_secs(10))
+    }
+
+    /// Marks that the links of pending runs are being checked for the given data file, or returns `None` if another check is already running.
+    pub(crate) fn try_lock_link_check(path: Option<&Path>) -> Result<Option<LockFile>, Error> {
+        LockFile::try_acquire(lock_path(path, "check-links")?, StdDuration::from_secs(60 * 60))
+    }
+
+    pub(crate) fn is_focused(&self, clock: Clock) -> bool {
+        self.focus_until.map_or(false, |focus_until| focus_until > clock.now())
+    }
+
+    pub(crate) fn is_game_snoozed(&self, game_name: &str, clock: Clock) -> bool {
+        self.snoozed_games.get(game_name).map_or(false, |&snoozed_until| snoozed_until > clock.now())
+    }
+
+    pub(crate) fn is_category_snoozed(&self, game_name: &str, cat_name: &str, clock: Clock) -> bool {
+        self.snoozed_categories.get(game_name)
+            .and_then(|categories| categories.get(cat_name))
+            .map_or(false, |&snoozed_until| snoozed_until > clock.now())
+    }
+
+    pub(crate) fn apply(&mut self, run_id: &str, action: Action, clock: Clock) {
+        let run_data = self.runs.entry(run_id.to_owned()).or_default();
+        match action {
+            Action::Check => run_data.mark_watched(clock),
+            Action::Defer(until) => { run_data.deferred = Some(until); }
+            Action::Unwatchable => { run_data.unwatchable = true; }
+        }
+    }
+
+    /// Whether `cleanup_downloads` would delete anything.
+    pub(crate) fn has_expired_downloads(&self, days: u64, clock: Clock) -> bool {
+        self.runs.values().any(|run_data| run_data.downloads_expired(days, clock))
+    }
+
+    /// Deletes the downloaded videos of runs that were watched, or marked as unwatchable, at least the given number of days ago.
+    pub(crate) fn cleanup_downloads(&mut self, days: u64, clock: Clock) -> Result<(), Error> {
+        for (run_id, run_data) in &mut self.runs {
+            if !run_data.downloads_expired(days, clock) { continue; }
+            for path in run_data.downloads.drain(..) {
+                info!("delete_download run={} path={}", run_id, path.display());
+                match fs::remove_file(&path) {
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {} // already deleted manually
+                    result => { result?; }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn save(&self) -> Result<(), Error> {
+        if let Some(ref path) = self.path {
+            serde_json::to_writer_pretty(File::create(path)?, self)?;
+            return Ok(());
+        }
         let dirs = xdg_basedir::get_data_home().into_iter().chain(xdg_basedir::get_data_dirs());
         for data_dir in dirs {
             let data_path = data_dir.join("bitbar/plugin-cache/srcomapi.json");
             if data_path.exists() {
                 if let Some(()) = File::create(data_path).ok()
-                    .and_then(|data_file| serde_json::to_writer_pretty(data_file, &self).ok())
+                    .and_then(|data_file| serde_json::to_writer_pretty(data_file, self).ok())
                 {
                     return Ok(());
                 }
@@ -48,7 +215,7 @@ impl Data {
         }
         let data_path = xdg_basedir::get_data_home()?.join("bitbar/plugin-cache/srcomapi.json");
         let data_file = File::create(data_path)?;
-        serde_json::to_writer_pretty(data_file, &self)?;
+        serde_json::to_writer_pretty(data_file, self)?;
         Ok(())
     }
 }
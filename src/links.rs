@@ -0,0 +1,71 @@
+//! Checking whether run videos are still available.
+
+use {
+    std::{
+        io,
+        path::Path,
+        process::Command
+    },
+    log::{
+        debug,
+        warn
+    },
+    reqwest::StatusCode,
+    crate::{
+        Error,
+        config::LinkCheck
+    }
+};
+
+/// Messages with which yt-dlp reports that a video is gone, as opposed to e.g. a network error.
+const YT_DLP_UNAVAILABLE: &[&str] = &[
+    "Video unavailable",
+    "Private video",
+    "This video has been removed",
+    "This video is private",
+    "HTTP Error 404",
+    "HTTP Error 410"
+];
+
+/// Checks whether the video at the given URL is available.
+///
+/// Returns `None` if this can't be determined, e.g. because the network is down or the downloader isn't installed.
+pub(crate) fn is_available(method: LinkCheck, http: &reqwest::Client, downloader: &Path, url: &str) -> Result<Option<bool>, Error> {
+    let available = match method {
+        LinkCheck::Off => None,
+        LinkCheck::Head => match http.head(url).send() {
+            Ok(response) => match response.status() {
+                StatusCode::NOT_FOUND | StatusCode::GONE => Some(false),
+                status if status.is_success() => Some(true),
+                status => {
+                    debug!("link_check url={} status={}", url, status);
+                    None
+                }
+            },
+            Err(e) => {
+                warn!("link_check url={} error={:?}", url, e.to_string());
+                None
+            }
+        },
+        LinkCheck::YtDlp => match Command::new(downloader).arg("--simulate").arg("--quiet").arg(url).output() {
+            Ok(output) => if output.status.success() {
+                Some(true)
+            } else {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                if YT_DLP_UNAVAILABLE.iter().any(|message| stderr.contains(message)) {
+                    Some(false)
+                } else {
+                    debug!("link_check url={} status={} stderr={:?}", url, output.status, stderr.trim());
+                    None
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                warn!("link_check url={} error=downloader_missing downloader={}", url, downloader.display());
+                None
+            }
+            Err(e) => return Err(e.into())
+        }
+    };
+    debug!("link_check url={} available={:?}", url, available);
+    Ok(available)
+}
@@ -0,0 +1,155 @@
+//! A minimal logger that appends to a size-rotated file in the XDG cache directory.
+//!
+//! Messages are written as a short event name followed by `key=value` pairs so they can be filtered with standard tools.
+
+use {
+    std::{
+        fs::{
+            self,
+            File,
+            OpenOptions
+        },
+        io::{
+            self,
+            prelude::*
+        },
+        os::unix::fs::MetadataExt as _,
+        path::{
+            Path,
+            PathBuf
+        },
+        sync::Mutex,
+        time::{
+            Instant,
+            SystemTime
+        }
+    },
+    chrono::prelude::*,
+    log::{
+        LevelFilter,
+        Log,
+        Metadata,
+        Record
+    },
+    crate::Error
+};
+
+/// The log file is rotated when it grows beyond this size in bytes.
+const MAX_SIZE: u64 = 1024 * 1024;
+/// The number of rotated log files kept in addition to the current one.
+const KEEP_FILES: usize = 3;
+
+/// The disk cache file used by the `srcomapi` client, if any. See `api_request`.
+static DISK_CACHE: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+struct FileLogger {
+    level: LevelFilter,
+    log_dir: PathBuf,
+    file: Mutex<File>
+}
+
+fn open(log_dir: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(log_dir.join("speedruncom.log"))
+}
+
+/// Whether `file` is still `speedruncom.log`, i.e. it hasn't been rotated away.
+fn is_current(file: &File, log_dir: &Path) -> bool {
+    match (file.metadata(), fs::metadata(log_dir.join("speedruncom.log"))) {
+        (Ok(opened), Ok(current)) => opened.dev() == current.dev() && opened.ino() == current.ino(),
+        (_, _) => false
+    }
+}
+
+/// Renames `speedruncom.log` to `speedruncom.log.1` and so on, dropping the oldest file, if the current file has grown too large.
+fn rotate(log_dir: &Path) -> io::Result<()> {
+    let log_path = log_dir.join("speedruncom.log");
+    if fs::metadata(&log_path).map_or(true, |metadata| metadata.len() <= MAX_SIZE) { return Ok(()); }
+    for i in (1..KEEP_FILES).rev() {
+        match fs::rename(log_dir.join(format!("speedruncom.log.{}", i)), log_dir.join(format!("speedruncom.log.{}", i + 1))) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            result => { result?; }
+        }
+    }
+    fs::rename(&log_path, log_dir.join("speedruncom.log.1"))
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if self.enabled(record.metadata()) {
+            if let Ok(mut file) = self.file.lock() {
+                let _ = writeln!(file, "{} {} {} {}", Utc::now().to_rfc3339(), record.level(), record.target(), record.args());
+                // long-running processes like the daemon would otherwise never rotate the log
+                let _ = rotate(&self.log_dir);
+                // reopen the file if it was rotated, either just now or by another process
+                if !is_current(&file, &self.log_dir) {
+                    if let Ok(new_file) = open(&self.log_dir) { *file = new_file; }
+                }
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Starts logging to `bitbar/speedruncom.log` in the XDG cache directory.
+///
+/// Debug messages, such as individual API requests and why runs were filtered, are only logged if `verbose` is set.
+pub(crate) fn init(verbose: bool) -> Result<(), Error> {
+    let log_dir = xdg_basedir::get_cache_home()?.join("bitbar");
+    fs::create_dir_all(&log_dir)?;
+    rotate(&log_dir)?;
+    let level = if verbose { LevelFilter::Debug } else { LevelFilter::Info };
+    log::set_boxed_logger(Box::new(FileLogger {
+        level,
+        file: Mutex::new(open(&log_dir)?),
+        log_dir
+    }))?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+/// Sets the disk cache file used by the `srcomapi` client, so that `api_request` can tell which requests were answered from it.
+pub(crate) fn watch_disk_cache(path: PathBuf) {
+    if let Ok(mut disk_cache) = DISK_CACHE.lock() {
+        *disk_cache = Some(path);
+    }
+}
+
+/// The modification time and size of the `srcomapi` disk cache file.
+fn disk_cache_state() -> Option<(SystemTime, u64)> {
+    let disk_cache = DISK_CACHE.lock().ok()?;
+    let metadata = fs::metadata(disk_cache.as_ref()?).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Runs a request made through the `srcomapi` client, logging its kind, the requested ID, and how long it took.
+///
+/// `srcomapi` writes its disk cache file whenever it downloads something, so if the file is unchanged after the request, it was answered from the cache and is logged as a cache hit instead.
+pub(crate) fn api_request<T>(kind: &str, id: &str, f: impl FnOnce() -> T) -> T {
+    let cache_before = disk_cache_state();
+    let start = Instant::now();
+    let result = f();
+    let duration_ms = start.elapsed().as_millis();
+    if cache_before.is_some() && cache_before == disk_cache_state() {
+        log::debug!("cache_hit kind={} id={} source=disk duration_ms={}", kind, id, duration_ms);
+    } else {
+        log::debug!("api_request kind={} id={} duration_ms={}", kind, id, duration_ms);
+    }
+    result
+}
+
+/// Runs a request that bypasses the `srcomapi` client, logging its kind, the requested path or URL, and how long it took.
+pub(crate) fn http_request<T>(kind: &str, id: &str, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    log::debug!("api_request kind={} id={} duration_ms={}", kind, id, start.elapsed().as_millis());
+    result
+}
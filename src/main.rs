@@ -2,23 +2,29 @@
 
 use {
     std::{
+        cell::RefCell,
         convert::Infallible,
         env::{
             self,
             current_exe
         },
         fmt,
-        fs::File,
+        fs::{
+            self,
+            File
+        },
         io::{
             self,
             prelude::*
         },
         iter,
-        path::Path,
+        path::PathBuf,
         process::{
             Command,
-            ExitStatus
-        }
+            ExitStatus,
+            Stdio
+        },
+        rc::Rc
     },
     bitbar::{
         ContentItem,
@@ -31,6 +37,11 @@ use {
     },
     css_color_parser::ColorParseError,
     derive_more::From,
+    log::{
+        debug,
+        info,
+        warn
+    },
     serde_json::Value as Json,
     srcomapi::{
         client::{
@@ -45,35 +56,75 @@ use {
             }
         }
     },
+    url::Url,
     crate::{
-        config::Config,
-        data::Data,
-        model::Game,
+        config::{
+            Config,
+            Format,
+            Handler,
+            LinkCheck
+        },
+        data::{
+            Action,
+            Data,
+            RunData,
+            VideoCheck
+        },
+        api::RawClient,
+        model::Runner,
         util::{
+            Clock,
             CommandStatusExt as _,
             Increment as _,
+            NatJoin as _,
             ResultNeverExt as _,
-            format_duration
+            fill_budget,
+            format_duration,
+            relative_date,
+            start_offset
         }
     }
 };
 
+mod api;
 mod config;
+mod cover;
+mod daemon;
 mod data;
+mod links;
+mod logging;
 mod model;
+mod playlist;
+mod server;
+mod tui;
 mod util;
 
-const IINA_PATH: &str = "/usr/local/bin/iina";
+pub(crate) const IINA_PATH: &str = "/usr/local/bin/iina";
+/// The port on which the `serve` subcommand listens unless `--port` is given.
+const DEFAULT_PORT: u16 = 8765;
 
 #[derive(Debug, From)]
 pub(crate) enum Error {
     Api(srcomapi::Error),
     Basedir(xdg_basedir::Error),
+    ChronoParse(chrono::ParseError),
     ColorParse(ColorParseError),
-    CommandExit(&'static str, ExitStatus),
+    /// the program's name for display, the path that was run, and its exit status
+    CommandExit(&'static str, PathBuf, ExitStatus),
+    ConflictingGame {
+        game_name: String,
+        path: PathBuf
+    },
+    DaemonRunning,
+    DownloaderOutput,
     EmptyTimespec,
     Fmt(fmt::Error),
+    Glob(glob::GlobError),
+    GlobPattern(glob::PatternError),
+    Image(image::ImageError),
     InvalidBinPath,
+    InvalidDateFormat(String),
+    InvalidIncludePath(PathBuf),
     Io(io::Error),
     MissingCliArg,
     MissingConfig,
@@ -81,9 +132,21 @@ pub(crate) enum Error {
         game_name: String,
         cat_name: String
     },
+    ParseInt(std::num::ParseIntError),
+    Reqwest(reqwest::Error),
     SerDe(serde_json::Error),
-    Timespec(timespec::Error),
-    UrlParse(url::ParseError)
+    Server(Box<dyn std::error::Error + Send + Sync>),
+    SetLogger(log::SetLoggerError),
+    Timespec(String, timespec::Error),
+    TomlDe(toml::de::Error),
+    TomlSer(toml::ser::Error),
+    TooManyCommandParams(Vec<String>),
+    UnknownConfigFormat(PathBuf),
+    UnknownOption(String),
+    UnknownPlaylistFormat(PathBuf),
+    UnknownSubcommand(String),
+    UrlParse(url::ParseError),
+    Yaml(serde_yaml::Error)
 }
 
 impl From<Infallible> for Error {
@@ -92,107 +155,431 @@ impl From<Infallible> for Error {
     }
 }
 
-fn bitbar() -> Result<Menu, Error> {
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Api(srcomapi::Error::Reqwest(e)) => write!(f, "API returned error: {}", e),
+            Error::Api(_) => write!(f, "unexpected response from the speedrun.com API"), // details are logged by error_menu
+            Error::Basedir(e) => write!(f, "failed to find XDG base directories: {}", e),
+            Error::ChronoParse(e) => write!(f, "invalid timestamp: {}", e),
+            Error::ColorParse(e) => write!(f, "failed to parse color: {}", e),
+            Error::CommandExit(name, _, status) => write!(f, "{} exited with {}", name, status),
+            Error::ConflictingGame { game_name, path } => write!(f, "game {} in {} is already configured", game_name, path.display()),
+            Error::DaemonRunning => write!(f, "a daemon is already running"),
+            Error::DownloaderOutput => write!(f, "the downloader did not print the path of the downloaded file"),
+            Error::EmptyTimespec => write!(f, "timespec matches no dates"),
+            Error::Fmt(e) => e.fmt(f),
+            Error::Glob(e) => write!(f, "failed to read included config file: {}", e),
+            Error::GlobPattern(e) => write!(f, "invalid include pattern: {}", e),
+            Error::Image(e) => write!(f, "failed to process cover art: {}", e),
+            Error::InvalidBinPath => write!(f, "the path to the plugin executable is not valid UTF-8"),
+            Error::InvalidDateFormat(date_format) => write!(f, "invalid date format: {:?}", date_format),
+            Error::InvalidIncludePath(path) => write!(f, "include path {} is not valid UTF-8", path.display()),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::MissingCliArg => write!(f, "missing command-line argument"),
+            Error::MissingConfig => write!(f, "missing configuration file"),
+            Error::NoSuchCategory { game_name, cat_name } => write!(f, "reference to unconfigured category {} in game {}", cat_name, game_name),
+            Error::ParseInt(e) => write!(f, "invalid number: {}", e),
+            Error::Reqwest(e) => write!(f, "HTTP error: {}", e),
+            Error::SerDe(e) => write!(f, "error in config or data file: {}", e),
+            Error::Server(e) => write!(f, "failed to start the dashboard server: {}", e),
+            Error::SetLogger(e) => write!(f, "failed to initialize logging: {}", e),
+            Error::Timespec(expr, e) => write!(f, "invalid timespec {:?}: {:?}", expr, e),
+            Error::TomlDe(e) => write!(f, "error in config file: {}", e),
+            Error::TomlSer(e) => write!(f, "failed to write TOML: {}", e),
+            Error::TooManyCommandParams(params) => write!(f, "too many parameters for a BitBar command: {:?}", params),
+            Error::UnknownConfigFormat(path) => write!(f, "unknown config file format: {}", path.display()),
+            Error::UnknownOption(option) => write!(f, "unknown option: {}", option),
+            Error::UnknownPlaylistFormat(path) => write!(f, "unknown playlist format: {}", path.display()),
+            Error::UnknownSubcommand(subcmd) => write!(f, "unknown subcommand: {}", subcmd),
+            Error::UrlParse(e) => write!(f, "failed to parse URL: {}", e),
+            Error::Yaml(e) => write!(f, "error in config file: {}", e)
+        }
+    }
+}
+
+impl Error {
+    /// A suggestion on how to fix this error, for display in the error menu.
+    fn hint(&self) -> String {
+        match self {
+            Error::Api(_) | Error::Reqwest(_) => "speedrun.com may be down or rate limiting. The plugin will try again on the next refresh.".into(),
+            Error::Basedir(_) => "Make sure the HOME environment variable is set.".into(),
+            Error::ChronoParse(_) => "Timestamps must be in RFC 3339 format, e.g. 2020-01-01T12:00:00Z, and quiet hours in HH:MM format, e.g. 09:00.".into(),
+            Error::CommandExit("the video handler", path, _) => format!("Check the handler {} in the config file.", path.display()),
+            Error::CommandExit("iina", path, _) => format!("Make sure IINA's command-line tool is installed at {}, or set player in the config file to its path.", path.display()),
+            Error::CommandExit(name, path, _) => format!("Make sure {} is installed at {} and working.", name, path.display()),
+            Error::ConflictingGame { game_name, .. } => format!("Remove one of the definitions of {} from the config file or its includes.", game_name),
+            Error::DaemonRunning => "Stop the running daemon before starting a new one.".into(),
+            Error::DownloaderOutput => "Make sure the configured downloader accepts the same arguments as yt-dlp.".into(),
+            Error::EmptyTimespec => "Specify a date in the future, e.g. r:7d for a week from now.".into(),
+            Error::Glob(_) | Error::GlobPattern(_) | Error::InvalidIncludePath(_) => "Check the include list in the config file.".into(),
+            Error::Image(_) => "Set coverArt to false in the config to disable cover art.".into(),
+            Error::InvalidBinPath => "Move the plugin to a path that's valid UTF-8.".into(),
+            Error::InvalidDateFormat(_) => "Change dateFormat in the config file to a date format in strftime syntax, e.g. %Y-%m-%d.".into(),
+            Error::Io(_) => "Make sure the config, data, and cache files are readable and writable.".into(),
+            Error::MissingCliArg | Error::ParseInt(_) | Error::UnknownOption(_) | Error::UnknownSubcommand(_) => "See the README for usage.".into(),
+            Error::MissingConfig => match expected_config_path() {
+                Some(path) => format!("Create a config file at {}.", path.display()),
+                None => "Create a config file at bitbar/plugins/speedruncom.json in your config directory.".into()
+            },
+            Error::NoSuchCategory { game_name, cat_name } => format!("Add a category named {} to {} in the config file, or remove the reference to it.", cat_name, game_name),
+            Error::SerDe(_) | Error::TomlDe(_) | Error::Yaml(_) => "Fix the syntax error in the file.".into(),
+            Error::Server(_) => "Make sure the port isn't already in use, or pick another one with --port.".into(),
+            Error::Timespec(expr, _) => format!("{:?} is not a valid timespec. Try something like r:7d.", expr),
+            Error::TomlSer(_) => "Convert the config to JSON or YAML instead.".into(),
+            Error::UnknownConfigFormat(_) => "Use a .json, .toml, or .yaml file extension.".into(),
+            Error::UnknownPlaylistFormat(_) => "Use a .m3u, .m3u8, or .xspf file extension.".into(),
+            Error::ColorParse(_) | Error::Fmt(_) | Error::SetLogger(_) | Error::TooManyCommandParams(_) | Error::UrlParse(_) => "This is a bug, please report it.".into()
+        }
+    }
+
+    /// Whether fixing this error involves editing the config file.
+    fn is_config_error(&self) -> bool {
+        match self {
+            Error::ConflictingGame { .. } | Error::Glob(_) | Error::GlobPattern(_) | Error::InvalidDateFormat(_) | Error::InvalidIncludePath(_) | Error::NoSuchCategory { .. } | Error::SerDe(_) | Error::TomlDe(_) | Error::UnknownConfigFormat(_) | Error::Yaml(_) => true,
+            _ => false
+        }
+    }
+}
+
+/// The path where a new config file should be created.
+fn expected_config_path() -> Option<PathBuf> {
+    xdg_basedir::get_config_home().ok().map(|cfg_dir| cfg_dir.join("bitbar/plugins/speedruncom.json"))
+}
+
+/// Resolves a relative path against the current working directory.
+fn absolute(path: PathBuf) -> Result<PathBuf, Error> {
+    Ok(if path.is_relative() { env::current_dir()?.join(path) } else { path })
+}
+
+/// Options that apply to all subcommands, specified on the command line before the subcommand.
+#[derive(Debug, Clone)]
+struct Options {
+    clock: Clock,
+    config_path: Option<PathBuf>,
+    data_path: Option<PathBuf>,
+    verbose: bool
+}
+
+impl Options {
+    /// Parses options from the command line, falling back to environment variables.
+    ///
+    /// Returns the subcommand, if any, as the first argument that's not an option.
+    ///
+    /// Relative config and data paths are made absolute, since menu commands and the daemon run in a different working directory.
+    fn parse(args: &mut env::Args) -> Result<(Options, Option<String>), Error> {
+        let mut options = Options {
+            clock: Clock::System,
+            config_path: env::var_os("BITBAR_SPEEDRUNCOM_CONFIG").map(PathBuf::from),
+            data_path: env::var_os("BITBAR_SPEEDRUNCOM_DATA").map(PathBuf::from),
+            verbose: false
+        };
+        let mut subcmd = None;
+        while let Some(arg) = args.next() {
+            let (flag, value) = match arg.find('=') {
+                Some(idx) if arg.starts_with("--") => (&arg[..idx], Some(arg[idx + 1..].to_owned())),
+                _ => (&arg[..], None)
+            };
+            match flag {
+                "--config" => { options.config_path = Some(value.or_else(|| args.next()).ok_or(Error::MissingCliArg)?.into()); }
+                "--data" => { options.data_path = Some(value.or_else(|| args.next()).ok_or(Error::MissingCliArg)?.into()); }
+                "--now" => { options.clock = Clock::Fixed(DateTime::parse_from_rfc3339(&value.or_else(|| args.next()).ok_or(Error::MissingCliArg)?)?.with_timezone(&Utc)); } // for previewing the menu at a different time
+                "--verbose" | "-v" => { options.verbose = true; }
+                _ => {
+                    subcmd = Some(arg);
+                    break;
+                }
+            }
+        }
+        options.config_path = options.config_path.map(absolute).transpose()?;
+        options.data_path = options.data_path.map(absolute).transpose()?;
+        Ok((options, subcmd))
+    }
+
+    fn config(&self) -> Result<Config, Error> {
+        Config::new(self.config_path.as_deref())
+    }
+
+    /// Whether focus mode or quiet hours are active. Errors loading the config or data are treated as neither being active.
+    fn is_quiet(&self) -> bool {
+        let focused = self.data().map_or(false, |data| data.is_focused(self.clock));
+        focused || self.config().ok().map_or(false, |config| config.is_quiet_hour(self.clock).unwrap_or(false))
+    }
+
+    fn data(&self) -> Result<Data, Error> {
+        Data::new(self.data_path.as_deref())
+    }
+
+    /// Loads the data, changes it, and saves it, while holding the data file's lock.
+    fn modify_data<T>(&self, f: impl FnOnce(&mut Data) -> T) -> Result<T, Error> {
+        let _lock = Data::lock(self.data_path.as_deref())?;
+        let mut data = self.data()?;
+        let result = f(&mut data);
+        data.save()?;
+        Ok(result)
+    }
+
+    /// The options which are passed on to other invocations of this plugin, so they use the same profile.
+    fn forwarded_args(&self) -> Vec<String> {
+        let mut args = Vec::default();
+        if let Some(ref config_path) = self.config_path {
+            args.push(format!("--config={}", config_path.display()));
+        }
+        if let Some(ref data_path) = self.data_path {
+            args.push(format!("--data={}", data_path.display()));
+        }
+        args
+    }
+
+    /// Returns a builder for menu commands which run subcommands of this plugin with the same options, or `None` if the plugin's path is unknown.
+    fn command_builder(&self) -> Option<CommandBuilder> {
+        Some(CommandBuilder {
+            bin: current_exe().ok()?,
+            forwarded_args: self.forwarded_args()
+        })
+    }
+}
+
+/// Builds BitBar commands which run subcommands of this plugin.
+struct CommandBuilder {
+    bin: PathBuf,
+    /// options passed before the subcommand, see `Options::forwarded_args`
+    forwarded_args: Vec<String>
+}
+
+impl CommandBuilder {
+    /// Returns a BitBar command which runs the given subcommand with its arguments.
+    fn command(&self, args: &[&str]) -> Result<bitbar::Command, Error> {
+        let mut params = vec![self.bin.to_str().ok_or(Error::InvalidBinPath)?.to_owned()];
+        params.extend(self.forwarded_args.iter().cloned());
+        params.extend(args.iter().map(|arg| arg.to_string()));
+        bitbar::Command::try_from(params).map_err(Error::TooManyCommandParams)
+    }
+}
+
+/// Loads the config and data and renders the plugin menu as of now.
+fn refresh(options: &Options) -> Result<Menu, Error> {
+    let config = options.config()?;
+    let (client, notifications) = get_client(&config)?;
+    let raw_client = RawClient::new(config.api_url().as_deref())?;
+    let cache = model::Cache::new(&client, &raw_client);
+    if let Err(e) = spawn_link_check(options, &config, &cache) {
+        warn!("spawn subcommand=check-links error={:?}", e.to_string()); // not worth replacing the menu with an error
+    }
+    if let Err(e) = cleanup_downloads(options, &config) {
+        warn!("cleanup_downloads error={:?}", e.to_string());
+    }
+    render(options, config, &cache, &raw_client, &notifications)
+}
+
+/// Starts checking the links of pending runs in the background if any of them are due for a check.
+///
+/// Checking links can take a while, so the results are shown on a later refresh.
+fn spawn_link_check(options: &Options, config: &Config, cache: &Rc<RefCell<model::Cache>>) -> Result<(), Error> {
+    if config.link_check == LinkCheck::Off { return Ok(()); }
+    let data = options.data()?;
+    let due = model::pending_games(cache, config.games.clone(), config.sort_by, &data, options.clock)?
+        .into_iter()
+        .flat_map(|pending_game| pending_game.runs)
+        .any(|(_, wr)| wr.videos().next().is_some() && data.runs.get(wr.id()).map_or(true, |run_data| run_data.needs_video_check(options.clock)));
+    if due {
+        debug!("spawn subcommand=check-links");
+        Command::new(current_exe()?).args(options.forwarded_args()).arg("check-links").stdout(Stdio::null()).stderr(Stdio::null()).spawn()?;
+    }
+    Ok(())
+}
+
+/// Deletes expired downloads if `deleteWatchedDownloadsAfter` is set.
+///
+/// The data file is only locked and rewritten if there's something to delete.
+fn cleanup_downloads(options: &Options, config: &Config) -> Result<(), Error> {
+    if let Some(days) = config.delete_watched_downloads_after {
+        if options.data()?.has_expired_downloads(days, options.clock) {
+            options.modify_data(|data| data.cleanup_downloads(days, options.clock))??;
+        }
+    }
+    Ok(())
+}
+
+/// Loads the data and renders the plugin menu as of now, using API responses from the given cache.
+fn render(options: &Options, config: Config, cache: &Rc<RefCell<model::Cache>>, raw_client: &RawClient, notifications: &[Notification]) -> Result<Menu, Error> {
+    let data = options.data()?;
+    bitbar(options.command_builder().as_ref(), config, &data, cache, raw_client, notifications, options.clock)
+}
+
+/// A run listed in the menu, as used for the watch time summary.
+struct ListedRun {
+    /// game and category name
+    label: String,
+    run_id: String,
+    time: std::time::Duration,
+    weblink: String,
+    opens_in_app: bool
+}
+
+/// Renders the plugin menu from the given state as of the given time.
+///
+/// Menu items that run subcommands of the plugin are only included if `commands` is given.
+fn bitbar(commands: Option<&CommandBuilder>, config: Config, data: &Data, cache: &Rc<RefCell<model::Cache>>, raw_client: &RawClient, notifications: &[Notification], clock: Clock) -> Result<Menu, Error> {
     let mut items = Vec::default();
     let mut total = Some(0);
-    let config = Config::new()?;
-    let data = Data::new()?;
-    let (client, notif_items, notif_total) = get_client(&config)?;
-    items.extend(notif_items);
-    total.incr_by(notif_total);
-    let cache = model::Cache::new(&client);
+    info!("refresh games={} now={}", config.games.len(), clock.now().to_rfc3339());
+    let quiet = data.is_focused(clock) || config.is_quiet_hour(clock)?;
+    if !notifications.is_empty() {
+        total.incr_by(Some(notifications.len()));
+        items.push(MenuItem::Sep);
+        for note in notifications {
+            items.push(ContentItem::new(&note)
+                .href(note.weblink().clone())?
+                .into()
+            );
+        }
+    }
     let mut game_sections = Vec::default();
-    let current_exe = current_exe();
-    for (game_name, game_config) in config.games {
-        let game = Game::new(cache.clone(), game_name, game_config);
+    let date_format = config.date_format().to_owned();
+    let (duration_style, trim_zero_millis) = (config.duration_style, config.trim_zero_millis);
+    let cover_art = config.cover_art;
+    let sort_by = config.sort_by;
+    let max_items = config.max_items;
+    let fill_minutes = config.fill_minutes.clone();
+    let today = clock.now().with_timezone(&Local).date().naive_local();
+    for model::PendingGame { game, key: game_key, runs: records } in model::pending_games(cache, config.games.clone(), sort_by, data, clock)? {
+        let game_name = game.name().to_owned();
         let mut game_total = Some(0);
-        let mut game_section = vec![
+        let src_games = game.src_games()?;
+        let mut game_section = Vec::default();
+        let mut game_runs = Vec::default();
+        let game_time = records.iter().map(|&(_, ref wr)| wr.time()).sum();
+        let mut header = ContentItem::new(format!("{} ({})", game, format_duration(game_time, duration_style, trim_zero_millis)));
+        if let Some(src_game) = src_games.first().filter(|_| cover_art) {
+            match cover::get(raw_client, src_game.id()) {
+                Ok(Some(png)) => { header = header.image(png)?; }
+                Ok(None) => {}
+                Err(e) => { warn!("cover_art game={} error={:?}", src_game.id(), e.to_string()); } // not worth replacing the menu with an error
+            }
+        }
+        let mut header_items = src_games.into_iter().map(|src_game| Ok(ContentItem::new(&src_game)
+            .href(src_game.weblink().clone())?
+            .alt(ContentItem::new(src_game.id()))
+            .into()
+        )).collect::<Result<Vec<_>, Error>>()?;
+        if let Some(commands) = commands {
+            header_items.push(MenuItem::Sep);
+            header_items.push(ContentItem::new("Snooze Game for a Day")
+                .command(commands.command(&["snooze-game", &game_name])?)
+                .into());
+            header_items.push(ContentItem::new("Snooze Game for a Week")
+                .command(commands.command(&["snooze-game", &game_name, "r:7d"])?)
+                .into());
+        }
+        let game_header = vec![
             MenuItem::Sep,
-            MenuItem::Content(ContentItem::new(&game)
-                .sub(game.src_games()?.into_iter().map(|src_game| Ok(ContentItem::new(&src_game)
-                    .href(src_game.weblink().clone())?
-                    .alt(ContentItem::new(src_game.id()))
-                    .into()
-                )).collect::<Result<Vec<_>, Error>>()?)
-            ),
+            MenuItem::Content(header.sub(header_items)),
         ];
-        let mut records = game.categories()
-            .into_iter()
-            .filter_map(|cat| cat.watchable_wrs(&data)
-                .map(|wrs_result| {
-                    if wrs_result.iter().any(|wr| data.runs.get(wr.id()).map_or(false, |run_data| run_data.watched)) {
-                        None // don't show runs that are tied with watched runs
-                    } else {
-                        wrs_result.into_iter()
-                            .filter(|wr| !data.runs.get(wr.id()).map_or(false, |run_data| run_data.deferred.map_or(false, |deferred_until| deferred_until > Utc::now())))
-                            .next()
-                    }.map(|wr| (cat, wr))
-                })
-                .transpose())
-            .collect::<Result<Vec<_>, _>>()?;
-        records.sort_by_key(|&(_, ref wr)| wr.time());
-        let fastest_time = records.first().map(|&(_, ref wr)| wr.time());
         for (cat, wr) in records {
             game_total.incr();
-            let wr_item = ContentItem::new(format!("New WR in {}: {}", cat, format_duration(wr.time())));
-            game_section.push(if let Ok(ref bin) = current_exe {
-                wr_item.sub(if wr.videos().next().is_some() {
-                    if Path::new(IINA_PATH).exists() {
-                        Box::new(iter::once(
+            let runners = cache.borrow_mut().runners(wr.id())?;
+            let video_unavailable = data.runs.get(wr.id()).map_or(false, RunData::is_video_unavailable);
+            let wr_item = ContentItem::new(format!(
+                "{}New WR in {}: {}{}",
+                if video_unavailable { "⚠ " } else { "" },
+                cat,
+                format_duration(wr.time(), duration_style, trim_zero_millis),
+                runners.iter().map(Runner::name).natjoin().map_or_else(String::default, |names| format!(" by {}", names))
+            ));
+            let local_videos = data.runs.get(wr.id()).and_then(RunData::local_videos);
+            let downloaded = local_videos.is_some();
+            // videos which are all opened in the default browser are linked directly, anything else goes through the watch subcommand
+            let opens_in_app = match local_videos {
+                Some(paths) => paths.iter().any(|path| config.handler(&path.display().to_string()) != Handler::Browser(None)),
+                None => wr.videos().any(|video| config.handler(&video.to_string()) != Handler::Browser(None))
+            };
+            game_runs.push(ListedRun { label: format!("{}: {}", game_name, cat), run_id: wr.id().to_string(), time: wr.time(), weblink: wr.weblink().to_string(), opens_in_app });
+            game_section.push(if let Some(commands) = commands {
+                let unavailable_item = if video_unavailable {
+                    Some(ContentItem::new("Video unavailable — mark unwatchable?")
+                        .command(commands.command(&["unwatchable", wr.id()])?)
+                        .into())
+                } else {
+                    None
+                };
+                wr_item.sub(unavailable_item.into_iter().chain(if wr.videos().next().is_some() {
+                    if opens_in_app {
+                        Box::new(vec![
                             ContentItem::new("Watch Run")
-                                .command((bin.to_str().ok_or(Error::InvalidBinPath)?, "watch", wr.id()))
-                                .into()
-                        )) as Box<dyn Iterator<Item = MenuItem>>
+                                .command(commands.command(&["watch", wr.id()])?)
+                                .into(),
+                            if downloaded {
+                                MenuItem::new("Downloaded for Offline")
+                            } else {
+                                ContentItem::new("Download for Offline")
+                                    .command(commands.command(&["download", wr.id()])?)
+                                    .into()
+                            }
+                        ].into_iter()) as Box<dyn Iterator<Item = MenuItem>>
                     } else {
                         let videos = wr.videos().collect::<Vec<_>>();
                         let single = videos.len() == 1;
                         Box::new(
                             videos.into_iter().enumerate().map(move |(i, video)|
                                 ContentItem::new(if single { format!("Watch Run") } else { format!("Watch Part {}", i + 1) })
-                                    .href(video.clone()).expect("failed to convert URL to URL") //TODO add support for opening certain websites in IINA
+                                    .href(video.clone()).expect("failed to convert URL to URL")
                                     .into()
                             )
                         )
                     }
                 } else {
                     Box::new(iter::empty())
-                }.chain({
+                }).chain({
                     let item = ContentItem::new("View Run Page")
                         .href(wr.weblink().clone())?
                         .into();
                     iter::once(item)
                 }).chain(
-                    wr.runners()?
-                        .into_iter()
-                        .map(|runner| MenuItem::new(format!("Runner: {}", runner)))
+                    runners.into_iter()
+                        .map(|runner| Ok(match runner {
+                            Runner::User { weblink: Some(ref weblink), .. } => ContentItem::new(format!("Runner: {}", runner.label())).href(&weblink[..])?.into(),
+                            _ => MenuItem::new(format!("Runner: {}", runner.label()))
+                        }))
+                        .collect::<Result<Vec<_>, Error>>()?
                 ).chain(vec![
-                    MenuItem::new(match wr.date() {
-                        Some(date) => format!("Recorded {}", date),
-                        None => "Recorded in the Old Days".into()
-                    }),
-                    MenuItem::new(match wr.status() {
-                        RunStatus::New => "Not yet verified".into(),
-                        RunStatus::Verified { verify_date: Some(date), .. } => format!("Verified {}", date),
-                        RunStatus::Verified { verify_date: None, .. } => "Verified in the Old Days".into(),
-                        RunStatus::Rejected { .. } => "REJECTED".into()
-                    }),
+                    match wr.date() {
+                        Some(date) => ContentItem::new(format!("Recorded {}", relative_date(date, today)))
+                            .alt(ContentItem::new(format!("Recorded {}", date.format(&date_format))))
+                            .into(),
+                        None => MenuItem::new("Recorded in the Old Days")
+                    },
+                    match wr.status() {
+                        RunStatus::New => MenuItem::new("Not yet verified"),
+                        RunStatus::Verified { verify_date: Some(date), .. } => {
+                            let date = date.with_timezone(&Local);
+                            ContentItem::new(format!("Verified {}", relative_date(date.date().naive_local(), today)))
+                                .alt(ContentItem::new(format!("Verified {}", date.format(&format!("{} %H:%M", date_format)))))
+                                .into()
+                        }
+                        RunStatus::Verified { verify_date: None, .. } => MenuItem::new("Verified in the Old Days"),
+                        RunStatus::Rejected { .. } => MenuItem::new("REJECTED")
+                    },
                     MenuItem::Sep,
                     ContentItem::new("Mark as Watched")
-                        .command([bin.to_str().ok_or(Error::InvalidBinPath)?, "check", wr.id()])
+                        .command(commands.command(&["check", wr.id()])?)
                         //.refresh() //TODO make sure multiple instances of bitbar-speedruncom running simultaneously works correctly, then uncomment this
                         .into(),
                     //TODO “mark as partially watched” submenu
                     ContentItem::new("Defer until Tomorrow")
-                        .command([bin.to_str().ok_or(Error::InvalidBinPath)?, "defer", wr.id()])
+                        .command(commands.command(&["defer", wr.id()])?)
                         //.refresh() //TODO make sure multiple instances of bitbar-speedruncom running simultaneously works correctly, then uncomment this
                         .into(),
                     ContentItem::new("Defer for a Week")
-                        .command([bin.to_str().ok_or(Error::InvalidBinPath)?, "defer", wr.id(), "r:7d"])
+                        .command(commands.command(&["defer", wr.id(), "r:7d"])?)
                         //.refresh() //TODO make sure multiple instances of bitbar-speedruncom running simultaneously works correctly, then uncomment this
                         .into(),
                     ContentItem::new("Mark as Unwatchable")
-                        .command([bin.to_str().ok_or(Error::InvalidBinPath)?, "unwatchable", wr.id()])
+                        .command(commands.command(&["unwatchable", wr.id()])?)
                         //.refresh() //TODO make sure multiple instances of bitbar-speedruncom running simultaneously works correctly, then uncomment this
+                        .into(),
+                    ContentItem::new("Snooze Category for a Day")
+                        .command(commands.command(&["snooze-category", &game_name, &cat.to_string()])?)
                         .into()
                 ]))
             } else {
@@ -206,19 +593,68 @@ fn bitbar() -> Result<Menu, Error> {
             writeln!(&mut game_text, "Unknown category ID {} in config|color=red", unknown_cat)?;
         }
         */
-        if game_total.map_or(true, |t| t > 0) {
-            total.incr_by(game_total);
-            let pos = game_sections.binary_search_by_key(&fastest_time, |&(time, _)| time).unwrap_or_else(|i| i);
-            game_sections.insert(pos, (fastest_time, game_section));
-        }
+        total.incr_by(game_total);
+        game_sections.push((game_key, game_header, game_section, game_runs));
     }
     //TODO check for any followed games not in config
-    for (_, game_section) in game_sections {
-        items.extend(game_section);
+    let mut remaining = max_items;
+    let mut overflow = Vec::default();
+    let mut overflow_count = 0;
+    let mut pending = Vec::default();
+    for (game_key, game_header, mut game_section, game_runs) in game_sections {
+        pending.extend(game_runs);
+        let shown = remaining.map_or(game_section.len(), |remaining| remaining.min(game_section.len()));
+        let hidden = game_section.split_off(shown);
+        if let Some(ref mut remaining) = remaining { *remaining -= shown; }
+        if shown > 0 || hidden.is_empty() {
+            items.extend(game_header);
+            items.extend(game_section);
+        }
+        if !hidden.is_empty() {
+            overflow_count += hidden.len();
+            if !overflow.is_empty() { overflow.push(MenuItem::Sep); }
+            overflow.push(MenuItem::new(game_key.name));
+            overflow.extend(hidden);
+        }
+    }
+    if !overflow.is_empty() {
+        items.push(MenuItem::Sep);
+        items.push(ContentItem::new(format!("More… ({})", overflow_count)).sub(overflow).into());
+    }
+    if !pending.is_empty() {
+        let total_time = pending.iter().map(|run| run.time).sum();
+        items.push(MenuItem::Sep);
+        items.push(MenuItem::new(format!("Total Watch Time: {}", format_duration(total_time, duration_style, trim_zero_millis))));
+        for &minutes in &fill_minutes {
+            let selected = fill_budget(pending.iter().map(|run| run.time), std::time::Duration::from_secs(minutes * 60));
+            if selected.is_empty() { continue; }
+            let selected_time = selected.iter().map(|&i| pending[i].time).sum();
+            let mut fill_items = vec![
+                MenuItem::new(format!("{} run{}, {}", selected.len(), if selected.len() == 1 { "" } else { "s" }, format_duration(selected_time, duration_style, trim_zero_millis))),
+                MenuItem::Sep
+            ];
+            for i in selected {
+                let run = &pending[i];
+                let item = ContentItem::new(format!("{} ({})", run.label, format_duration(run.time, duration_style, trim_zero_millis)));
+                fill_items.push(match commands {
+                    Some(commands) if run.opens_in_app => item.command(commands.command(&["watch", &run.run_id])?).into(),
+                    _ => item.href(&run.weblink[..])?.into()
+                });
+            }
+            items.push(ContentItem::new(format!("Fill {} Minutes", minutes)).sub(fill_items).into());
+        }
+    }
+    if let Some(commands) = commands {
+        items.push(MenuItem::Sep);
+        items.push(if data.is_focused(clock) {
+            ContentItem::new("End Focus Mode").command(commands.command(&["focus", "off"])?).into()
+        } else {
+            ContentItem::new("Focus for an Hour").command(commands.command(&["focus"])?).into()
+        });
     }
     Ok(if total.map_or(true, |total| total > 0) {
         iter::once(
-            MenuItem::Content(ContentItem::new(total.map_or("?".into(), |total| total.to_string()))
+            MenuItem::Content(ContentItem::new(if quiet { String::default() } else { total.map_or("?".into(), |total| total.to_string()) })
                 .template_image(&include_bytes!("../assets/trophy.png")[..])?
             )
         ).chain(items).collect()
@@ -227,7 +663,8 @@ fn bitbar() -> Result<Menu, Error> {
     })
 }
 
-fn get_client(config: &Config) -> Result<(Client, Vec<MenuItem>, Option<usize>), Error> {
+/// Builds an API client from the config, along with the user's unread notifications if an API key is configured.
+fn get_client(config: &Config) -> Result<(Client, Vec<Notification>), Error> {
     let mut client_builder = client::Builder::new(concat!("bitbar-speedruncom/", env!("CARGO_PKG_VERSION")))
         .cache_timeout(Duration::hours(12)..Duration::hours(24));
     if let Ok(cache) = xdg_basedir::get_cache_home() {
@@ -235,137 +672,382 @@ fn get_client(config: &Config) -> Result<(Client, Vec<MenuItem>, Option<usize>),
         if File::open(&cache_path).map_err(Error::Io).and_then(|cache_file| serde_json::from_reader::<_, Json>(cache_file).map_err(Error::SerDe)).is_err() {
             writeln!(File::create(&cache_path)?, "{{}}")?;
         }
+        debug!("disk_cache path={}", cache_path.display());
+        logging::watch_disk_cache(cache_path.clone());
         client_builder = client_builder.disk_cache(cache_path)?;
     };
+    if let Some(api_url) = config.api_url() {
+        debug!("api_url url={}", api_url);
+        client_builder = client_builder.base_url(api_url)?;
+    }
     let client_builder = client_builder.num_tries(4);
-    let mut items = Vec::default();
-    let mut total = Some(0);
-    Ok((if let Some(ref key) = config.api_key {
+    let api_key = env::var("BITBAR_SPEEDRUNCOM_API_KEY").ok().or_else(|| config.api_key.clone());
+    Ok(if let Some(ref key) = api_key {
         let auth_client = client_builder.auth(&key).build()?;
-        let notifications = Notification::list::<Vec<_>>(&auth_client)?.into_iter().filter(|note| !note.read()).collect::<Vec<_>>();
-        if !notifications.is_empty() {
-            total.incr_by(Some(notifications.len()));
-            items.push(MenuItem::Sep);
-            for note in notifications {
-                items.push(ContentItem::new(&note)
-                    .href(note.weblink().clone())?
-                    .into()
-                );
-            }
-        }
-        auth_client.into()
+        let notifications = logging::api_request("notifications", "", || Notification::list::<Vec<_>>(&auth_client))?.into_iter().filter(|note| !note.read()).collect();
+        (auth_client.into(), notifications)
     } else {
-        client_builder.build()?
-    }, items, total))
+        (client_builder.build()?, Vec::default())
+    })
 }
 
-fn notify(summary: impl fmt::Display, body: impl fmt::Display) -> ! {
-    //let _ = notify_rust::set_application(&notify_rust::get_bundle_identifier_or_default("BitBar")); //TODO uncomment when https://github.com/h4llow3En/mac-notification-sys/issues/8 is fixed
-    let _ = notify_rust::Notification::default()
-        .summary(&summary.to_string())
-        .sound_name("Funk")
-        .body(&body.to_string())
-        .show();
+/// Shows a desktop notification, unless `quiet` is set, then panics with the same message.
+fn notify(quiet: bool, summary: impl fmt::Display, body: impl fmt::Display) -> ! {
+    if !quiet {
+        //let _ = notify_rust::set_application(&notify_rust::get_bundle_identifier_or_default("BitBar")); //TODO uncomment when https://github.com/h4llow3En/mac-notification-sys/issues/8 is fixed
+        let _ = notify_rust::Notification::default()
+            .summary(&summary.to_string())
+            .sound_name("Funk")
+            .body(&body.to_string())
+            .show();
+    }
     panic!("{}: {}", summary, body);
 }
 
 trait ResultExt {
     type Ok;
 
-    fn notify(self, summary: impl fmt::Display) -> Self::Ok;
+    fn notify(self, options: &Options, summary: impl fmt::Display) -> Self::Ok;
 }
 
-impl<T, E: fmt::Debug> ResultExt for Result<T, E> {
+impl<T, E: fmt::Display> ResultExt for Result<T, E> {
     type Ok = T;
 
-    fn notify(self, summary: impl fmt::Display) -> T {
+    fn notify(self, options: &Options, summary: impl fmt::Display) -> T {
         match self {
             Ok(t) => t,
-            Err(e) => { notify(summary, format!("{:?}", e)); }
+            Err(e) => { notify(options.is_quiet(), summary, e); }
         }
     }
 }
 
-fn check(mut args: env::Args) -> Result<(), Error> {
-    let mut data = Data::new()?;
-    data.runs.entry(args.next().ok_or(Error::MissingCliArg)?).or_default().watched = true;
-    data.save()?;
-    Ok(())
+fn check(options: &Options, mut args: impl Iterator<Item = String>) -> Result<(), Error> {
+    let run_id = args.next().ok_or(Error::MissingCliArg)?;
+    options.modify_data(|data| data.apply(&run_id, Action::Check, options.clock))
 }
 
-fn defer(mut args: env::Args) -> Result<(), Error> {
-    let mut data = Data::new()?;
-    let mut run = data.runs.entry(args.next().ok_or(Error::MissingCliArg)?).or_default();
-    let mut args = args.peekable();
-    run.deferred = Some(if args.peek().is_some() {
-        timespec::next(args)?.ok_or(Error::EmptyTimespec)?
+/// Checks whether the videos of pending runs are still available and records the results in the data file.
+///
+/// Runs that were checked within the last day are skipped unless `--force` is given.
+fn check_links(options: &Options, mut args: impl Iterator<Item = String>) -> Result<(), Error> {
+    let force = args.any(|arg| arg == "--force");
+    let _lock = if let Some(lock) = Data::try_lock_link_check(options.data_path.as_deref())? {
+        lock
     } else {
-        Utc::now() + Duration::days(1)
-    });
-    data.save()?;
-    Ok(())
+        info!("check_links_skipped reason=already_running");
+        return Ok(());
+    };
+    let config = options.config()?;
+    let data = options.data()?;
+    let client = get_client(&config)?.0;
+    let raw_client = RawClient::new(config.api_url().as_deref())?;
+    let cache = model::Cache::new(&client, &raw_client);
+    let method = if config.link_check == LinkCheck::Off { LinkCheck::Head } else { config.link_check };
+    let downloader = config.downloader().to_owned();
+    let http = reqwest::Client::new();
+    let runs = model::pending_games(&cache, config.games, config.sort_by, &data, options.clock)?
+        .into_iter()
+        .flat_map(|pending_game| pending_game.runs.into_iter().map(|(_, wr)| wr))
+        .collect::<Vec<_>>();
+    let mut checks = Vec::default();
+    for wr in runs {
+        if wr.videos().next().is_none() { continue; } // nothing to check
+        if !force && data.runs.get(wr.id()).map_or(false, |run_data| !run_data.needs_video_check(options.clock)) { continue; }
+        let mut available = Some(false);
+        for video_url in wr.videos() {
+            match links::is_available(method, &http, &downloader, &video_url.to_string())? {
+                Some(true) => { available = Some(true); break; }
+                Some(false) => {}
+                None => { available = None; }
+            }
+        }
+        // inconclusive results are recorded too, so the check isn't started again on every refresh
+        info!("check_links run={} available={:?}", wr.id(), available);
+        checks.push((wr.id().to_string(), VideoCheck { checked_at: options.clock.now(), available }));
+    }
+    // checking can take minutes, so only the results are merged into the current data to keep any changes made in the meantime
+    options.modify_data(|data| {
+        for (run_id, check) in checks {
+            data.runs.entry(run_id).or_default().video_check = Some(check);
+        }
+    })
 }
 
-fn unwatchable(mut args: env::Args) -> Result<(), Error> {
-    let mut data = Data::new()?;
-    data.runs.entry(args.next().ok_or(Error::MissingCliArg)?).or_default().unwatchable = true;
-    data.save()?;
+fn config_cmd(options: &Options, mut args: impl Iterator<Item = String>) -> Result<(), Error> {
+    match &args.next().ok_or(Error::MissingCliArg)?[..] {
+        "convert" => {
+            let first = PathBuf::from(args.next().ok_or(Error::MissingCliArg)?);
+            let (input, output) = if let Some(second) = args.next() {
+                (first, PathBuf::from(second))
+            } else {
+                (Config::path(options.config_path.as_deref())?, first)
+            };
+            Format::from_path(&output)?; // make sure the output format is known before reading the input
+            Config::load_without_includes(&input)?.save_as(&output)?;
+        }
+        subcmd => { return Err(Error::UnknownSubcommand(subcmd.into())); }
+    }
     Ok(())
 }
 
-fn watch(mut args: env::Args) -> Result<(), Error> {
-    let client = get_client(&Config::new()?)?.0;
-    let run = Run::from_id(&client, args.next().ok_or(Error::MissingCliArg)?)?;
-    for video_url in run.videos() {
-        Command::new(IINA_PATH)
-            .arg("--separate-windows")
-            .arg("--no-stdin")
-            .arg("--keep-running")
+fn defer(options: &Options, mut args: impl Iterator<Item = String>) -> Result<(), Error> {
+    let run_id = args.next().ok_or(Error::MissingCliArg)?;
+    let until = parse_until(options, args, Duration::days(1))?;
+    options.modify_data(|data| data.apply(&run_id, Action::Defer(until), options.clock))
+}
+
+/// Downloads a run's videos into the library for offline viewing.
+fn download(options: &Options, mut args: impl Iterator<Item = String>) -> Result<(), Error> {
+    let config = options.config()?;
+    let client = get_client(&config)?.0;
+    let run_id = args.next().ok_or(Error::MissingCliArg)?;
+    let run = logging::api_request("run", &run_id, || Run::from_id(&client, &run_id))?;
+    let library = config.library()?;
+    fs::create_dir_all(&library)?;
+    let mut downloads = Vec::default();
+    for (i, video_url) in run.videos().enumerate() {
+        info!("download run={} video={}", run.id(), video_url);
+        let output = Command::new(config.downloader())
+            .arg("--no-simulate")
+            .arg("--print").arg("after_move:filepath")
+            .arg("--output").arg(library.join(format!("{}-{}.%(ext)s", run.id(), i + 1)))
             .arg(video_url.to_string())
-            .check("iina")?;
+            .output()?;
+        if !output.status.success() { return Err(Error::CommandExit("yt-dlp", config.downloader().to_owned(), output.status)); }
+        let path = String::from_utf8_lossy(&output.stdout).lines().map(str::trim).filter(|line| !line.is_empty()).last().map(PathBuf::from).ok_or(Error::DownloaderOutput)?;
+        downloads.push(path);
+    }
+    options.modify_data(|data| { data.runs.entry(run_id).or_default().downloads = downloads; })
+}
+
+/// Writes the pending runs, optionally filtered by game and category, to a playlist file.
+fn export_playlist(options: &Options, mut args: impl Iterator<Item = String>) -> Result<(), Error> {
+    let mut game_filter = None;
+    let mut cat_filter = None;
+    let mut mark_watched = false;
+    let mut output = None;
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--game" => { game_filter = Some(args.next().ok_or(Error::MissingCliArg)?); }
+            "--category" => { cat_filter = Some(args.next().ok_or(Error::MissingCliArg)?); }
+            "--mark-watched" => { mark_watched = true; }
+            _ => { output = Some(PathBuf::from(arg)); }
+        }
+    }
+    let output = output.ok_or(Error::MissingCliArg)?;
+    let format = playlist::Format::from_path(&output)?; // make sure the format is known before querying the API
+    let config = options.config()?;
+    let data = options.data()?;
+    let client = get_client(&config)?.0;
+    let raw_client = RawClient::new(config.api_url().as_deref())?;
+    let cache = model::Cache::new(&client, &raw_client);
+    let (duration_style, trim_zero_millis) = (config.duration_style, config.trim_zero_millis);
+    let mut entries = Vec::default();
+    let mut exported = Vec::default();
+    for model::PendingGame { game, runs, .. } in model::pending_games(&cache, config.games, config.sort_by, &data, options.clock)? {
+        if game_filter.as_ref().map_or(false, |game_name| game_name != game.name()) { continue; }
+        for (cat, wr) in runs {
+            if cat_filter.as_ref().map_or(false, |cat_name| *cat_name != cat.to_string()) { continue; }
+            let videos = wr.videos().collect::<Vec<_>>();
+            if videos.is_empty() { continue; }
+            let runners = cache.borrow_mut().runners(wr.id())?;
+            let title = format!(
+                "{} – {} – {}{}",
+                game,
+                cat,
+                format_duration(wr.time(), duration_style, trim_zero_millis),
+                runners.iter().map(Runner::name).natjoin().map_or_else(String::default, |names| format!(" by {}", names))
+            );
+            let single = videos.len() == 1;
+            for (i, video) in videos.into_iter().enumerate() {
+                entries.push(playlist::Entry {
+                    title: if single { title.clone() } else { format!("{} (part {})", title, i + 1) },
+                    location: video.to_string(),
+                    duration: if single { Some(wr.time()) } else { None }
+                });
+            }
+            exported.push(wr.id().to_string());
+        }
+    }
+    format.write(&entries, File::create(&output)?)?;
+    info!("export_playlist path={} runs={}", output.display(), exported.len());
+    if mark_watched {
+        options.modify_data(|data| {
+            for run_id in exported {
+                data.apply(&run_id, Action::Check, options.clock);
+            }
+        })?;
     }
-    let mut data = Data::new()?;
-    data.runs.entry(run.id().to_string()).or_default().watched = true;
-    data.save()?;
     Ok(())
 }
 
+/// Parses the remaining arguments as a timespec, defaulting to the given duration from now.
+///
+/// The `timespec` crate always starts from the system time, so with a fixed clock the result is moved by the difference between the two.
+fn parse_until(options: &Options, args: impl Iterator<Item = String>, default: Duration) -> Result<DateTime<Utc>, Error> {
+    let timespec = args.collect::<Vec<_>>();
+    Ok(if !timespec.is_empty() {
+        let system_now = Utc::now();
+        let until = timespec::next(timespec.clone()).map_err(|e| Error::Timespec(timespec.join(" "), e))?.ok_or(Error::EmptyTimespec)?;
+        match options.clock {
+            Clock::System => until,
+            Clock::Fixed(now) => now + (until - system_now)
+        }
+    } else {
+        options.clock.now() + default
+    })
+}
+
+fn focus(options: &Options, args: impl Iterator<Item = String>) -> Result<(), Error> {
+    let mut args = args.peekable();
+    let focus_until = if args.peek().map_or(false, |arg| arg == "off") {
+        None
+    } else {
+        Some(parse_until(options, args, Duration::hours(1))?)
+    };
+    options.modify_data(|data| { data.focus_until = focus_until; })
+}
+
+fn serve(options: &Options, mut args: impl Iterator<Item = String>) -> Result<(), Error> {
+    let mut port = DEFAULT_PORT;
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--port" => { port = args.next().ok_or(Error::MissingCliArg)?.parse()?; }
+            _ => { return Err(Error::UnknownOption(arg)); }
+        }
+    }
+    server::serve(options, port)
+}
+
+fn snooze_category(options: &Options, mut args: impl Iterator<Item = String>) -> Result<(), Error> {
+    let game_name = args.next().ok_or(Error::MissingCliArg)?;
+    let cat_name = args.next().ok_or(Error::MissingCliArg)?;
+    let until = parse_until(options, args, Duration::days(1))?;
+    options.modify_data(|data| { data.snoozed_categories.entry(game_name).or_default().insert(cat_name, until); })
+}
+
+fn snooze_game(options: &Options, mut args: impl Iterator<Item = String>) -> Result<(), Error> {
+    let game_name = args.next().ok_or(Error::MissingCliArg)?;
+    let until = parse_until(options, args, Duration::days(1))?;
+    options.modify_data(|data| { data.snoozed_games.insert(game_name, until); })
+}
+
+fn unwatchable(options: &Options, mut args: impl Iterator<Item = String>) -> Result<(), Error> {
+    let run_id = args.next().ok_or(Error::MissingCliArg)?;
+    options.modify_data(|data| data.apply(&run_id, Action::Unwatchable, options.clock))
+}
+
+fn watch(options: &Options, mut args: impl Iterator<Item = String>) -> Result<(), Error> {
+    let config = options.config()?;
+    let run_id = args.next().ok_or(Error::MissingCliArg)?;
+    let local_videos = options.data()?.runs.get(&run_id).and_then(RunData::local_videos).map(|paths| paths.iter().map(|path| path.display().to_string()).collect::<Vec<_>>());
+    let videos = if let Some(local_videos) = local_videos {
+        local_videos
+    } else {
+        let client = get_client(&config)?.0;
+        let run = logging::api_request("run", &run_id, || Run::from_id(&client, &run_id))?;
+        run.videos().map(|video_url| video_url.to_string()).collect::<Vec<_>>()
+    };
+    for video in videos {
+        info!("watch run={} video={}", run_id, video);
+        match config.handler(&video) {
+            Handler::Player => {
+                let mut cmd = Command::new(config.player());
+                cmd.arg("--separate-windows");
+                cmd.arg("--no-stdin");
+                cmd.arg("--keep-running");
+                if let Some(offset) = Url::parse(&video).ok().as_ref().and_then(start_offset) {
+                    cmd.arg(format!("--mpv-start={}", offset.as_secs())); // not every player honors timestamps in the URL itself
+                }
+                cmd.arg(video).check("iina")?;
+            }
+            Handler::Browser(None) => { Command::new("/usr/bin/open").arg(video).check("open")?; }
+            Handler::Browser(Some(app)) => { Command::new("/usr/bin/open").arg("-a").arg(app).arg(video).check("open")?; }
+            Handler::Program(program) => { Command::new(program).arg(video).check("the video handler")?; } // the start offset isn't passed since the program's options are unknown
+        }
+    }
+    // the player may have been open for a while, so the data is only loaded now to keep any changes made in the meantime
+    options.modify_data(|data| data.apply(&run_id, Action::Check, options.clock))
+}
+
+fn error_menu(options: &Options, e: Error) -> Menu {
+    warn!("error_menu error={:?}", e);
+    let mut error_menu = vec![
+        ContentItem::new("?").template_image(&include_bytes!("../assets/trophy.png")[..]).never_unwrap().into(),
+        MenuItem::Sep,
+        MenuItem::new(&e),
+        ContentItem::new(e.hint()).color("gray").expect("failed to parse the color gray").into()
+    ];
+    if let Error::Api(srcomapi::Error::Reqwest(ref e)) = e {
+        if let Some(url) = e.url() {
+            error_menu.push(ContentItem::new(format!("URL: {}", url))
+                .href(url.clone()).expect("failed to add link to error menu")
+                .color("blue").expect("failed to parse the color blue")
+                .into());
+        }
+    }
+    if e.is_config_error() {
+        if let Ok(config_path) = Config::path(options.config_path.as_deref()) {
+            error_menu.push(MenuItem::Sep);
+            error_menu.push(ContentItem::new("Open Config File").command(("/usr/bin/open", config_path.display())).into());
+        }
+    }
+    match e {
+        Error::ConflictingGame { ref path, .. } => {
+            error_menu.push(ContentItem::new("Open Included File").command(("/usr/bin/open", path.display())).into());
+        }
+        Error::MissingConfig => if let Some(cfg_dir) = expected_config_path().as_ref().and_then(|path| path.parent()) {
+            error_menu.push(MenuItem::Sep);
+            error_menu.push(ContentItem::new("Open Config Folder").command(("/usr/bin/open", cfg_dir.display())).into());
+        },
+        Error::ColorParse(_) | Error::Fmt(_) | Error::SetLogger(_) | Error::TooManyCommandParams(_) | Error::UrlParse(_) => {
+            error_menu.push(MenuItem::Sep);
+            error_menu.push(ContentItem::new("Report a Bug")
+                .href("https://github.com/fenhl/bitbar-speedruncom/issues/new").expect("failed to add link to error menu")
+                .into());
+        }
+        _ => {}
+    }
+    Menu(error_menu)
+}
+
 fn main() {
     let mut args = env::args();
     let _ = args.next(); // ignore executable name
-    if let Some(arg) = args.next() {
+    let (options, subcmd) = Options::parse(&mut args).unwrap_or_else(|e| notify(false, "error parsing command line", e));
+    let _ = logging::init(options.verbose); // the plugin should still work if the log file can't be opened
+    let args = args.collect::<Vec<_>>();
+    if let Some(arg) = subcmd {
+        if daemon::SUBCOMMANDS.contains(&&arg[..]) {
+            if let Some(result) = daemon::request(&options, iter::once(arg.clone()).chain(args.iter().cloned()).collect()) {
+                result.notify(&options, format!("error in {} cmd", arg));
+                return;
+            }
+        }
+        let args = args.into_iter();
         match &arg[..] {
-            "check" => { check(args).notify("error in check cmd"); }
-            "defer" => { defer(args).notify("error in defer cmd"); }
-            "unwatchable" => { unwatchable(args).notify("error in unwatchable cmd"); }
-            "watch" => { watch(args).notify("error in watch cmd"); }
-            subcmd => { panic!("unknown subcommand: {:?}", subcmd); }
+            "check" => { check(&options, args).notify(&options, "error in check cmd"); }
+            "check-links" => { check_links(&options, args).notify(&options, "error in check-links cmd"); }
+            "config" => { config_cmd(&options, args).notify(&options, "error in config cmd"); }
+            "daemon" => { daemon::run(&options).notify(&options, "error in daemon cmd"); }
+            "defer" => { defer(&options, args).notify(&options, "error in defer cmd"); }
+            "download" => { download(&options, args).notify(&options, "error in download cmd"); }
+            "export-playlist" => { export_playlist(&options, args).notify(&options, "error in export-playlist cmd"); }
+            "focus" => { focus(&options, args).notify(&options, "error in focus cmd"); }
+            "serve" => { serve(&options, args).notify(&options, "error in serve cmd"); }
+            "snooze-category" => { snooze_category(&options, args).notify(&options, "error in snooze-category cmd"); }
+            "snooze-game" => { snooze_game(&options, args).notify(&options, "error in snooze-game cmd"); }
+            "tui" => { tui::run(&options).notify(&options, "error in tui cmd"); }
+            "unwatchable" => { unwatchable(&options, args).notify(&options, "error in unwatchable cmd"); }
+            "watch" => { watch(&options, args).notify(&options, "error in watch cmd"); }
+            _ => { notify(options.is_quiet(), "error parsing command line", Error::UnknownSubcommand(arg.clone())); }
         }
+    } else if let Some(Ok(menu)) = daemon::request(&options, Vec::default()) {
+        print!("{}", menu);
     } else {
-        match bitbar() {
+        match refresh(&options) {
             Ok(menu) => { print!("{}", menu); }
-            Err(e) => {
-                let mut error_menu = vec![
-                    ContentItem::new("?").template_image(&include_bytes!("../assets/trophy.png")[..]).never_unwrap().into(),
-                    MenuItem::Sep
-                ];
-                match e {
-                    Error::Api(srcomapi::Error::Reqwest(e)) => {
-                        error_menu.push(MenuItem::new(format!("API returned error: {}", e)));
-                        if let Some(url) = e.url() {
-                            error_menu.push(ContentItem::new(format!("URL: {}", url))
-                                .href(url.clone()).expect("failed to add link to error menu")
-                                .color("blue").expect("failed to parse the color blue")
-                                .into());
-                        }
-                    }
-                    Error::MissingConfig => { error_menu.push(MenuItem::new(format!("missing or invalid configuration file"))); } //TODO better error message
-                    Error::NoSuchCategory { game_name, cat_name } => { error_menu.push(MenuItem::new(format!("reference to unconfigured category {} in game {}", cat_name, game_name))); }
-                    Error::SerDe(e) => { error_menu.push(MenuItem::new(format!("error in config file: {}", e))); }
-                    e => { error_menu.push(MenuItem::new(format!("{:?}", e))); } //TODO handle separately
-                }
-                print!("{}", Menu(error_menu));
-            }
+            Err(e) => { print!("{}", error_menu(&options, e)); }
         }
     }
 }
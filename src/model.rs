@@ -1,15 +1,23 @@
 use {
     std::{
         cell::RefCell,
-        collections::HashMap,
+        collections::{
+            BTreeMap,
+            BTreeSet,
+            HashMap
+        },
         fmt,
         iter::{
             self,
             FromIterator as _
         },
-        rc::Rc
+        rc::Rc,
+        time::Duration
     },
+    chrono::prelude::*,
     itertools::Itertools as _,
+    log::debug,
+    serde_json::Value as Json,
     srcomapi::{
         client::Client,
         model::{
@@ -19,54 +27,181 @@ use {
             },
             game::Game as SrcGame,
             level::Level,
-            run::Run,
+            run::{
+                Run,
+                RunStatus
+            },
             variable::Filter
         }
     },
     crate::{
         Error,
+        api::RawClient,
         config::{
+            Config,
             ConfigCategory,
-            ConfigGame
+            ConfigGame,
+            SortBy,
+            SortKey
         },
-        data::Data
+        data::Data,
+        logging,
+        util::Clock
     }
 };
 
+/// A participant of a run.
+#[derive(Debug, Clone)]
+pub(crate) enum Runner {
+    User {
+        name: String,
+        weblink: Option<String>,
+        /// ISO 3166 country code, possibly followed by a slash and a subdivision code
+        country_code: Option<String>,
+        pronouns: Option<String>
+    },
+    /// Someone without a speedrun.com account
+    Guest {
+        name: String
+    }
+}
+
+impl Runner {
+    fn from_user(user: &Json) -> Runner {
+        Runner::User {
+            name: user["names"]["international"].as_str().or_else(|| user["id"].as_str()).unwrap_or("?").to_owned(),
+            weblink: user["weblink"].as_str().map(str::to_owned),
+            country_code: user["location"]["country"]["code"].as_str().map(str::to_owned),
+            pronouns: user["pronouns"].as_str().filter(|pronouns| !pronouns.is_empty()).map(str::to_owned)
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            Runner::User { name, .. } | Runner::Guest { name } => name
+        }
+    }
+
+    /// How the runner is described in run details, e.g. `🇨🇦 Runner One (they/them)` or `Runner Two (guest)`.
+    pub(crate) fn label(&self) -> String {
+        match self {
+            Runner::User { pronouns, .. } => {
+                let mut label = String::default();
+                if let Some(flag) = self.flag() {
+                    label += &flag;
+                    label.push(' ');
+                }
+                label += self.name();
+                if let Some(pronouns) = pronouns {
+                    label += &format!(" ({})", pronouns);
+                }
+                label
+            }
+            Runner::Guest { name } => format!("{} (guest)", name)
+        }
+    }
+
+    /// The flag emoji of the runner's country, if known.
+    pub(crate) fn flag(&self) -> Option<String> {
+        if let Runner::User { country_code: Some(country_code), .. } = self {
+            let country = country_code.split('/').next()?;
+            if country.len() == 2 && country.chars().all(|c| c.is_ascii_alphabetic()) {
+                // each letter maps to a regional indicator symbol, and pairs of those are rendered as flags
+                return country.chars().map(|c| std::char::from_u32(0x1f1e6 + (c.to_ascii_lowercase() as u32 - 'a' as u32))).collect();
+            }
+        }
+        None
+    }
+}
+
+impl fmt::Display for Runner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.name().fmt(f)
+    }
+}
+
 pub(crate) struct Cache {
     client: Client,
+    raw_client: RawClient,
     src_categories: HashMap<String, SrcCategory>,
     src_games: HashMap<String, SrcGame>,
     levels: HashMap<String, Level>,
+    runners: HashMap<String, Vec<Runner>>,
+    /// The IDs of the runs which were marked as unwatchable when `wrs` was filled.
+    unwatchable: BTreeSet<String>,
     wrs: HashMap<(String, String), Vec<Run>>
 }
 
 impl Cache {
-    pub(crate) fn new(client: &Client) -> Rc<RefCell<Cache>> {
+    pub(crate) fn new(client: &Client, raw_client: &RawClient) -> Rc<RefCell<Cache>> {
         Rc::new(RefCell::new(Cache {
             client: client.clone(),
+            raw_client: raw_client.clone(),
             levels: HashMap::default(),
+            runners: HashMap::default(),
             src_categories: HashMap::default(),
             src_games: HashMap::default(),
+            unwatchable: BTreeSet::default(),
             wrs: HashMap::default()
         }))
     }
 
+    /// Forgets the cached world records if the set of runs marked as unwatchable has changed since they were cached, e.g. by another process.
+    fn sync_unwatchable(&mut self, data: &Data) {
+        let unwatchable = data.runs.iter().filter(|(_, run_data)| run_data.unwatchable).map(|(run_id, _)| run_id.clone()).collect::<BTreeSet<_>>();
+        if unwatchable != self.unwatchable {
+            debug!("cache_invalidate kind=wrs");
+            self.wrs.clear();
+            self.unwatchable = unwatchable;
+        }
+    }
+
+    /// Returns the runners of the run with the given ID, including guests.
+    pub(crate) fn runners(&mut self, run_id: &str) -> Result<Vec<Runner>, Error> {
+        if let Some(runners) = self.runners.get(run_id) {
+            debug!("cache_hit kind=runners id={}", run_id);
+            return Ok(runners.clone());
+        }
+        let run = self.raw_client.get(&format!("runs/{}", run_id))?;
+        let mut runners = Vec::default();
+        for player in run["players"].as_array().map_or(&[][..], Vec::as_slice) {
+            match (player["rel"].as_str(), player["id"].as_str()) {
+                (Some("user"), Some(user_id)) => runners.push(Runner::from_user(&self.raw_client.get(&format!("users/{}", user_id))?)),
+                (Some("guest"), _) => runners.push(Runner::Guest { name: player["name"].as_str().unwrap_or("?").to_owned() }),
+                (rel, _) => { debug!("unknown_player run={} rel={:?}", run_id, rel); }
+            }
+        }
+        self.runners.insert(run_id.to_owned(), runners.clone());
+        Ok(runners)
+    }
+
     fn src_category(&mut self, cat_id: &str) -> Result<SrcCategory, Error> {
-        if let Some(cat) = self.src_categories.get(cat_id) { return Ok(cat.clone()); }
-        self.src_categories.insert(cat_id.to_string(), SrcCategory::from_id(&self.client, cat_id)?);
+        if let Some(cat) = self.src_categories.get(cat_id) {
+            debug!("cache_hit kind=category id={}", cat_id);
+            return Ok(cat.clone());
+        }
+        let client = &self.client;
+        self.src_categories.insert(cat_id.to_string(), logging::api_request("category", cat_id, || SrcCategory::from_id(client, cat_id))?);
         Ok(self.src_categories[cat_id].clone())
     }
 
     fn src_game(&mut self, game_id: &str) -> Result<SrcGame, Error> {
-        if let Some(game) = self.src_games.get(game_id) { return Ok(game.clone()); }
-        self.src_games.insert(game_id.to_string(), SrcGame::from_id(&self.client, game_id)?);
+        if let Some(game) = self.src_games.get(game_id) {
+            debug!("cache_hit kind=game id={}", game_id);
+            return Ok(game.clone());
+        }
+        let client = &self.client;
+        self.src_games.insert(game_id.to_string(), logging::api_request("game", game_id, || SrcGame::from_id(client, game_id))?);
         Ok(self.src_games[game_id].clone())
     }
 
     fn level(&mut self, level_id: &str) -> Result<Level, Error> {
-        if let Some(level) = self.levels.get(level_id) { return Ok(level.clone()); }
-        self.levels.insert(level_id.to_string(), Level::from_id(&self.client, level_id)?);
+        if let Some(level) = self.levels.get(level_id) {
+            debug!("cache_hit kind=level id={}", level_id);
+            return Ok(level.clone());
+        }
+        let client = &self.client;
+        self.levels.insert(level_id.to_string(), logging::api_request("level", level_id, || Level::from_id(client, level_id))?);
         Ok(self.levels[level_id].clone())
     }
 }
@@ -82,6 +217,10 @@ impl Game {
         Game { cache, name, config }
     }
 
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
     pub(crate) fn src_games(&self) -> Result<Vec<SrcGame>, Error> {
         self.config.src_games.keys().map(|game_id| self.cache.borrow_mut().src_game(game_id)).collect()
     }
@@ -123,7 +262,11 @@ impl Category {
     }
 
     pub(crate) fn watchable_wrs(&self, data: &Data) -> Result<Vec<Run>, Error> {
-        if let Some(runs) = self.cache.borrow().wrs.get(&(self.game_name.clone(), self.name.clone())) { return Ok(runs.clone()); }
+        self.cache.borrow_mut().sync_unwatchable(data);
+        if let Some(runs) = self.cache.borrow().wrs.get(&(self.game_name.clone(), self.name.clone())) {
+            debug!("cache_hit kind=wrs game={:?} category={:?}", self.game_name, self.name);
+            return Ok(runs.clone());
+        }
         let mut wrs = Vec::default();
         let subcategories = if self.config()?.variable_state.is_empty() {
             Box::new(self.src_categories()?.into_iter().map(|cat| (None, cat)))
@@ -138,23 +281,28 @@ impl Category {
         };
         for (filter, src_cat) in subcategories {
             let lbs: Box<dyn Iterator<Item = srcomapi::Result<Vec<Run>>>> = if src_cat.is_il() {
-                Box::new(self.levels()?.into_iter().map(|level| if let Some(ref filter) = filter {
+                Box::new(self.levels()?.into_iter().map(|level| logging::api_request("leaderboard", &format!("{}/{}", level.id(), src_cat.id()), || if let Some(ref filter) = filter {
                     (&level, &src_cat).filtered_leaderboard::<Vec<_>>(filter)
                 } else {
                     (&level, &src_cat).leaderboard::<Vec<_>>()
-                }))
+                })))
             } else {
-                Box::new(iter::once(if let Some(filter) = filter {
+                Box::new(iter::once(logging::api_request("leaderboard", src_cat.id(), || if let Some(filter) = filter {
                     src_cat.filtered_leaderboard::<Vec<_>>(&filter)
                 } else {
                     src_cat.leaderboard::<Vec<_>>()
-                })) as Box<dyn Iterator<Item = _>>
+                }))) as Box<dyn Iterator<Item = _>>
             };
             for lb in lbs {
                 wrs.extend({
                     lb?
                         .into_iter()
-                        .filter(|run| !data.runs.get(run.id()).map_or(false, |run_data| run_data.unwatchable))
+                        .filter(|run| if data.runs.get(run.id()).map_or(false, |run_data| run_data.unwatchable) {
+                            debug!("filtered_run reason=unwatchable run={} game={:?} category={:?}", run.id(), self.game_name, self.name);
+                            false
+                        } else {
+                            true
+                        })
                         .scan(None, |fastest_time, run| match *fastest_time {
                             Some(t) => if run.time() > t { None } else { Some(run) },
                             None => {
@@ -175,7 +323,12 @@ impl Category {
             wrs.extend(sub_wrs);
         }
         let runs = if let Some(fastest_time) = wrs.iter().map(|run| run.time()).min() {
-            wrs.into_iter().filter(|run| run.time() == fastest_time).collect()
+            wrs.into_iter().filter(|run| if run.time() == fastest_time {
+                true
+            } else {
+                debug!("filtered_run reason=slower_subcategory run={} game={:?} category={:?}", run.id(), self.game_name, self.name);
+                false
+            }).collect()
         } else {
             Vec::default()
         };
@@ -189,3 +342,109 @@ impl fmt::Display for Category {
         self.name.fmt(f)
     }
 }
+
+/// A followed game with at least one pending run.
+pub(crate) struct PendingGame {
+    pub(crate) game: Game,
+    pub(crate) key: SortKey,
+    /// the pending run of each category that has one, in menu order
+    pub(crate) runs: Vec<(Category, Run)>
+}
+
+/// Resolves the pending runs of all followed games, skipping anything that's snoozed, watched, or deferred.
+///
+/// Games and the categories within them are sorted according to `sort_by`.
+pub(crate) fn pending_games(cache: &Rc<RefCell<Cache>>, games: BTreeMap<String, ConfigGame>, sort_by: SortBy, data: &Data, clock: Clock) -> Result<Vec<PendingGame>, Error> {
+    let mut pending = Vec::<PendingGame>::default();
+    for (game_name, game_config) in games {
+        if data.is_game_snoozed(&game_name, clock) {
+            debug!("filtered_game reason=snoozed game={:?}", game_name);
+            continue;
+        }
+        let priority = game_config.priority;
+        let game = Game::new(cache.clone(), game_name.clone(), game_config);
+        let mut runs = game.categories()
+            .into_iter()
+            .filter(|cat| if data.is_category_snoozed(&game_name, &cat.to_string(), clock) {
+                debug!("filtered_category reason=snoozed game={:?} category={:?}", game_name, cat.to_string());
+                false
+            } else {
+                true
+            })
+            .filter_map(|cat| cat.watchable_wrs(data)
+                .map(|wrs_result| {
+                    if let Some(watched) = wrs_result.iter().find(|wr| data.runs.get(wr.id()).map_or(false, |run_data| run_data.watched)) {
+                        debug!("filtered_category reason=tied_with_watched game={:?} category={:?} watched_run={}", game_name, cat.to_string(), watched.id());
+                        None // don't show runs that are tied with watched runs
+                    } else {
+                        wrs_result.into_iter()
+                            .filter(|wr| match data.runs.get(wr.id()) {
+                                Some(run_data) if run_data.is_deferred(clock) => {
+                                    debug!("filtered_run reason=deferred run={} deferred_until={:?}", wr.id(), run_data.deferred);
+                                    false
+                                }
+                                _ => true
+                            })
+                            .next()
+                    }.map(|wr| (cat, wr))
+                })
+                .transpose())
+            .collect::<Result<Vec<_>, _>>()?;
+        if runs.is_empty() { continue; }
+        runs.sort_by(|&(ref cat1, ref wr1), &(ref cat2, ref wr2)| sort_by.compare(
+            &SortKey { name: cat1.to_string(), priority: 0, fastest_time: Some(wr1.time()), newest_date: wr1.date() },
+            &SortKey { name: cat2.to_string(), priority: 0, fastest_time: Some(wr2.time()), newest_date: wr2.date() }
+        ));
+        let key = SortKey {
+            name: game_name,
+            priority,
+            fastest_time: runs.iter().map(|&(_, ref wr)| wr.time()).min(),
+            newest_date: runs.iter().filter_map(|&(_, ref wr)| wr.date()).max()
+        };
+        let pos = pending.binary_search_by(|other| sort_by.compare(&other.key, &key)).unwrap_or_else(|i| i);
+        pending.insert(pos, PendingGame { game, key, runs });
+    }
+    Ok(pending)
+}
+
+/// A pending run along with everything the dashboard and the terminal interface show about it.
+pub(crate) struct PendingRun {
+    pub(crate) id: String,
+    pub(crate) game: String,
+    pub(crate) category: String,
+    pub(crate) time: Duration,
+    pub(crate) runners: Vec<Runner>,
+    pub(crate) date: Option<NaiveDate>,
+    /// the verification status, with the verification time in the local time zone
+    pub(crate) status: String,
+    pub(crate) videos: Vec<String>,
+    pub(crate) weblink: String
+}
+
+/// Resolves the pending runs of each game with a fresh cache, so runs that were just checked or deferred are left out.
+pub(crate) fn pending_runs(client: &Client, raw_client: &RawClient, config: &Config, data: &Data, clock: Clock) -> Result<Vec<(String, Vec<PendingRun>)>, Error> {
+    let cache = Cache::new(client, raw_client);
+    pending_games(&cache, config.games.clone(), config.sort_by, data, clock)?
+        .into_iter()
+        .map(|pending_game| {
+            let game = pending_game.game.name().to_owned();
+            let runs = pending_game.runs.into_iter().map(|(cat, wr)| Ok(PendingRun {
+                id: wr.id().to_string(),
+                game: game.clone(),
+                category: cat.to_string(),
+                time: wr.time(),
+                runners: cache.borrow_mut().runners(wr.id())?,
+                date: wr.date(),
+                status: match wr.status() {
+                    RunStatus::New => format!("Not yet verified"),
+                    RunStatus::Verified { verify_date: Some(date), .. } => format!("Verified {}", date.with_timezone(&Local).format(&format!("{} %H:%M", config.date_format()))),
+                    RunStatus::Verified { verify_date: None, .. } => format!("Verified in the Old Days"),
+                    RunStatus::Rejected { .. } => format!("REJECTED")
+                },
+                videos: wr.videos().map(|video| video.to_string()).collect(),
+                weblink: wr.weblink().to_string()
+            })).collect::<Result<_, Error>>()?;
+            Ok((game, runs))
+        })
+        .collect()
+}
@@ -0,0 +1,68 @@
+//! Playlists of pending runs, for queueing them in other video players.
+
+use {
+    std::{
+        io::{
+            self,
+            prelude::*
+        },
+        path::Path,
+        time::Duration
+    },
+    crate::{
+        Error,
+        util::xml_escape
+    }
+};
+
+pub(crate) struct Entry {
+    pub(crate) title: String,
+    pub(crate) location: String,
+    /// unknown for runs whose video is split into multiple parts
+    pub(crate) duration: Option<Duration>
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Format {
+    M3u,
+    Xspf
+}
+
+impl Format {
+    pub(crate) fn from_path(path: &Path) -> Result<Format, Error> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("m3u") | Some("m3u8") => Ok(Format::M3u),
+            Some("xspf") => Ok(Format::Xspf),
+            _ => Err(Error::UnknownPlaylistFormat(path.to_owned()))
+        }
+    }
+
+    pub(crate) fn write(&self, entries: &[Entry], mut out: impl Write) -> io::Result<()> {
+        match self {
+            Format::M3u => {
+                writeln!(out, "#EXTM3U")?;
+                for entry in entries {
+                    writeln!(out, "#EXTINF:{},{}", entry.duration.map_or(-1, |duration| duration.as_secs() as i64), entry.title.replace('\n', " "))?;
+                    writeln!(out, "{}", entry.location)?;
+                }
+            }
+            Format::Xspf => {
+                writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+                writeln!(out, r#"<playlist version="1" xmlns="http://xspf.org/ns/0/">"#)?;
+                writeln!(out, "  <trackList>")?;
+                for entry in entries {
+                    writeln!(out, "    <track>")?;
+                    writeln!(out, "      <location>{}</location>", xml_escape(&entry.location))?;
+                    writeln!(out, "      <title>{}</title>", xml_escape(&entry.title))?;
+                    if let Some(duration) = entry.duration {
+                        writeln!(out, "      <duration>{}</duration>", duration.as_millis())?;
+                    }
+                    writeln!(out, "    </track>")?;
+                }
+                writeln!(out, "  </trackList>")?;
+                writeln!(out, "</playlist>")?;
+            }
+        }
+        Ok(())
+    }
+}
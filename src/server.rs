@@ -0,0 +1,164 @@
+//! A dashboard of pending runs, served over HTTP on localhost.
+
+use {
+    std::{
+        fmt::Write as _,
+        io::Cursor
+    },
+    chrono::Duration,
+    log::{
+        info,
+        warn
+    },
+    serde_json::{
+        Value as Json,
+        json
+    },
+    srcomapi::client::Client,
+    tiny_http::{
+        Header,
+        Method,
+        Request,
+        Response,
+        Server
+    },
+    crate::{
+        Error,
+        Options,
+        api::RawClient,
+        config::Config,
+        data::Action,
+        model::{
+            self,
+            PendingRun,
+            Runner
+        },
+        util::{
+            NatJoin as _,
+            format_duration,
+            xml_escape
+        }
+    }
+};
+
+fn api_pending(games: &[(String, Vec<PendingRun>)], config: &Config) -> Json {
+    games.iter().map(|(game_name, runs)| json!({
+        "game": game_name,
+        "runs": runs.iter().map(|run| json!({
+            "id": run.id,
+            "category": run.category,
+            "time": run.time.as_secs_f64(),
+            "formattedTime": format_duration(run.time, config.duration_style, config.trim_zero_millis),
+            "runners": run.runners.iter().map(Runner::name).collect::<Vec<_>>(),
+            "weblink": run.weblink,
+            "videos": run.videos,
+            "date": run.date.map(|date| date.format("%Y-%m-%d").to_string())
+        })).collect::<Vec<_>>()
+    })).collect()
+}
+
+fn dashboard(games: &[(String, Vec<PendingRun>)], config: &Config) -> Result<String, Error> {
+    let mut html = String::default();
+    writeln!(&mut html, r#"<!DOCTYPE html>"#)?;
+    writeln!(&mut html, r#"<html>"#)?;
+    writeln!(&mut html, r#"<head><meta charset="utf-8"><title>Pending WRs</title><style>body {{ font-family: sans-serif; }} form {{ display: inline; }}</style></head>"#)?;
+    writeln!(&mut html, r#"<body>"#)?;
+    writeln!(&mut html, r#"<h1>Pending WRs</h1>"#)?;
+    if games.is_empty() {
+        writeln!(&mut html, r#"<p>You're all caught up!</p>"#)?;
+    }
+    for (game_name, runs) in games {
+        writeln!(&mut html, r#"<h2>{}</h2>"#, xml_escape(game_name))?;
+        writeln!(&mut html, r#"<ul>"#)?;
+        for run in runs {
+            write!(&mut html, r#"<li><a href="{}">{}</a>: {}"#, xml_escape(&run.weblink), xml_escape(&run.category), xml_escape(&format_duration(run.time, config.duration_style, config.trim_zero_millis)))?;
+            if let Some(names) = run.runners.iter().map(Runner::name).natjoin() {
+                write!(&mut html, " by {}", xml_escape(&names))?;
+            }
+            if let Some(date) = run.date {
+                write!(&mut html, " ({})", date.format(config.date_format()))?;
+            }
+            for (i, video) in run.videos.iter().enumerate() {
+                write!(&mut html, r#" <a href="{}">{}</a>"#, xml_escape(video), if run.videos.len() == 1 { format!("Watch") } else { format!("Part {}", i + 1) })?;
+            }
+            for (action, label) in &[("check", "Mark as Watched"), ("defer", "Defer until Tomorrow"), ("unwatchable", "Mark as Unwatchable")] {
+                write!(&mut html, r#" <form method="post" action="/runs/{}/{}"><button>{}</button></form>"#, xml_escape(&run.id), action, label)?;
+            }
+            writeln!(&mut html, "</li>")?;
+        }
+        writeln!(&mut html, r#"</ul>"#)?;
+    }
+    writeln!(&mut html, r#"</body>"#)?;
+    writeln!(&mut html, r#"</html>"#)?;
+    Ok(html)
+}
+
+fn header(header: &str) -> Header {
+    header.parse().expect("invalid HTTP header")
+}
+
+/// Whether the request was addressed to this server by name and, if it comes from a web page, by the dashboard itself.
+///
+/// This keeps other websites open in the browser from changing the data file, either by posting forms to the dashboard or through DNS rebinding.
+fn is_same_origin(request: &Request, port: u16) -> bool {
+    let hosts = [format!("127.0.0.1:{}", port), format!("localhost:{}", port)];
+    let header_value = |name: &str| request.headers().iter().find(|header| header.field.equiv(name)).map(|header| header.value.as_str().to_owned());
+    header_value("Host").map_or(false, |host| hosts.contains(&host))
+        && header_value("Origin").map_or(true, |origin| hosts.iter().any(|host| origin == format!("http://{}", host)))
+}
+
+fn handle(options: &Options, config: &Config, client: &Client, raw_client: &RawClient, port: u16, request: &Request) -> Result<Response<Cursor<Vec<u8>>>, Error> {
+    if !is_same_origin(request, port) {
+        warn!("serve url={} error=\"cross-origin request\"", request.url());
+        return Ok(Response::from_string("forbidden").with_status_code(403));
+    }
+    let path = request.url().splitn(2, '?').next().unwrap_or_default().to_owned();
+    let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+    Ok(match (request.method(), &segments[..]) {
+        (&Method::Get, [""]) => Response::from_string(dashboard(&model::pending_runs(client, raw_client, config, &options.data()?, options.clock)?, config)?)
+            .with_header(header("Content-Type: text/html; charset=utf-8")),
+        (&Method::Get, ["api", "pending"]) => Response::from_string(api_pending(&model::pending_runs(client, raw_client, config, &options.data()?, options.clock)?, config).to_string())
+            .with_header(header("Content-Type: application/json")),
+        (&Method::Post, ["runs", run_id, action]) | (&Method::Post, ["api", "runs", run_id, action]) => {
+            let action = match *action {
+                "check" => Action::Check,
+                "defer" => Action::Defer(options.clock.now() + Duration::days(1)),
+                "unwatchable" => Action::Unwatchable,
+                _ => return Ok(Response::from_string("not found").with_status_code(404))
+            };
+            info!("serve action={:?} run={}", action, run_id);
+            options.modify_data(|data| data.apply(run_id, action, options.clock))?;
+            if segments[0] == "api" {
+                Response::from_string(json!({"ok": true}).to_string())
+                    .with_header(header("Content-Type: application/json"))
+            } else {
+                Response::from_string("")
+                    .with_status_code(303)
+                    .with_header(header("Location: /"))
+            }
+        }
+        _ => Response::from_string("not found").with_status_code(404)
+    })
+}
+
+/// Serves the dashboard until the process is killed.
+///
+/// The server only listens on localhost and only accepts requests from its own pages, since anyone who can reach it can modify the data file.
+pub(crate) fn serve(options: &Options, port: u16) -> Result<(), Error> {
+    let config = options.config()?;
+    let client = crate::get_client(&config)?.0;
+    let raw_client = RawClient::new(config.api_url().as_deref())?;
+    let server = Server::http(("127.0.0.1", port)).map_err(Error::Server)?;
+    let port = server.server_addr().port(); // the actual port, in case port 0 was requested
+    println!("listening on http://{}", server.server_addr());
+    for request in server.incoming_requests() {
+        let response = handle(options, &config, &client, &raw_client, port, &request).unwrap_or_else(|e| {
+            warn!("serve url={} error={:?}", request.url(), e.to_string());
+            Response::from_string(e.to_string()).with_status_code(500)
+        });
+        if let Err(e) = request.respond(response) {
+            warn!("serve error={:?}", e.to_string());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,199 @@
+//! A full-screen terminal interface for working through many pending runs at once.
+
+use {
+    std::{
+        env,
+        io::{
+            self,
+            prelude::*
+        },
+        process::{
+            Command,
+            Stdio
+        }
+    },
+    crossterm::{
+        cursor,
+        event::{
+            self,
+            Event,
+            KeyCode,
+            KeyEvent,
+            KeyModifiers
+        },
+        execute,
+        queue,
+        style::{
+            Attribute,
+            Print,
+            SetAttribute
+        },
+        terminal::{
+            self,
+            ClearType,
+            EnterAlternateScreen,
+            LeaveAlternateScreen
+        }
+    },
+    srcomapi::client::Client,
+    crate::{
+        Error,
+        Options,
+        api::RawClient,
+        config::Config,
+        model::{
+            self,
+            PendingRun
+        },
+        util::format_duration
+    }
+};
+
+enum Row {
+    Game(String),
+    Run(PendingRun)
+}
+
+fn load(options: &Options, config: &Config, client: &Client, raw_client: &RawClient) -> Result<Vec<Row>, Error> {
+    let mut rows = Vec::default();
+    for (game, runs) in model::pending_runs(client, raw_client, config, &options.data()?, options.clock)? {
+        rows.push(Row::Game(game));
+        rows.extend(runs.into_iter().map(Row::Run));
+    }
+    Ok(rows)
+}
+
+/// The indices of the rows which can be selected.
+fn run_rows(rows: &[Row]) -> Vec<usize> {
+    rows.iter().enumerate().filter(|(_, row)| if let Row::Run(_) = row { true } else { false }).map(|(i, _)| i).collect()
+}
+
+/// Truncates text to the given number of characters.
+fn fit(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
+
+fn details(run: &PendingRun, config: &Config) -> Vec<String> {
+    let mut lines = vec![
+        run.game.clone(),
+        run.category.clone(),
+        format!("Time: {}", format_duration(run.time, config.duration_style, config.trim_zero_millis)),
+        String::default()
+    ];
+    lines.extend(run.runners.iter().map(|runner| format!("Runner: {}", runner.label())));
+    lines.push(match run.date {
+        Some(date) => format!("Recorded {}", date.format(config.date_format())),
+        None => format!("Recorded in the Old Days")
+    });
+    lines.push(run.status.clone());
+    lines.push(String::default());
+    lines.extend(run.videos.iter().cloned());
+    lines.push(run.weblink.clone());
+    lines
+}
+
+fn draw(out: &mut impl Write, config: &Config, rows: &[Row], selected: Option<usize>, status: &str) -> Result<(), Error> {
+    let (width, height) = terminal::size()?;
+    let (width, height) = (usize::from(width), usize::from(height));
+    let list_width = width * 3 / 5;
+    let details_x = list_width + 2;
+    let list_height = height.saturating_sub(2);
+    let scroll = selected.map_or(0, |row| (row + 1).saturating_sub(list_height));
+    queue!(out, terminal::Clear(ClearType::All))?;
+    if rows.is_empty() {
+        queue!(out, cursor::MoveTo(0, 0), Print("No pending runs."))?;
+    }
+    for (i, row) in rows.iter().enumerate().skip(scroll).take(list_height) {
+        queue!(out, cursor::MoveTo(0, (i - scroll) as u16))?;
+        match row {
+            Row::Game(name) => { queue!(out, SetAttribute(Attribute::Bold), Print(fit(name, list_width)), SetAttribute(Attribute::Reset))?; }
+            Row::Run(run) => {
+                let text = fit(&format!("  {}: {}", run.category, format_duration(run.time, config.duration_style, config.trim_zero_millis)), list_width);
+                if selected == Some(i) {
+                    queue!(out, SetAttribute(Attribute::Reverse), Print(text), SetAttribute(Attribute::Reset))?;
+                } else {
+                    queue!(out, Print(text))?;
+                }
+            }
+        }
+    }
+    if let Some(Row::Run(run)) = selected.map(|row| &rows[row]) {
+        for (y, line) in details(run, config).into_iter().enumerate().take(list_height) {
+            queue!(out, cursor::MoveTo(details_x as u16, y as u16), Print(fit(&line, width.saturating_sub(details_x))))?;
+        }
+    }
+    queue!(
+        out,
+        cursor::MoveTo(0, height.saturating_sub(1) as u16),
+        SetAttribute(Attribute::Dim),
+        Print(fit(&format!("↑↓ select  w watch  c check  d defer  u unwatchable  r reload  q quit  {}", status), width)),
+        SetAttribute(Attribute::Reset)
+    )?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Restores the terminal when dropped, even if the TUI exits with an error.
+struct RawTerminal;
+
+impl RawTerminal {
+    fn enter() -> Result<RawTerminal, Error> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, cursor::Hide)?;
+        Ok(RawTerminal)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+pub(crate) fn run(options: &Options) -> Result<(), Error> {
+    let config = options.config()?;
+    let client = crate::get_client(&config)?.0;
+    let raw_client = RawClient::new(config.api_url().as_deref())?;
+    let bin = env::current_exe()?;
+    let mut rows = load(options, &config, &client, &raw_client)?;
+    let mut position = 0; // index into the selectable rows
+    let mut status = String::default();
+    let _terminal = RawTerminal::enter()?;
+    let mut stdout = io::stdout();
+    loop {
+        let run_rows = run_rows(&rows);
+        position = position.min(run_rows.len().saturating_sub(1));
+        let selected = run_rows.get(position).copied();
+        draw(&mut stdout, &config, &rows, selected, &status)?;
+        let subcmd = match event::read()? {
+            Event::Key(KeyEvent { code: KeyCode::Char('c'), modifiers: KeyModifiers::CONTROL }) => break,
+            Event::Key(KeyEvent { code, .. }) => match code {
+                KeyCode::Char('q') | KeyCode::Esc => break,
+                KeyCode::Up | KeyCode::Char('k') => { position = position.saturating_sub(1); continue; }
+                KeyCode::Down | KeyCode::Char('j') => { position += 1; continue; }
+                KeyCode::Char('r') => {
+                    rows = load(options, &config, &client, &raw_client)?;
+                    status = format!("reloaded");
+                    continue;
+                }
+                KeyCode::Char('w') => "watch",
+                KeyCode::Char('c') => "check",
+                KeyCode::Char('d') => "defer",
+                KeyCode::Char('u') => "unwatchable",
+                _ => continue
+            },
+            _ => continue
+        };
+        if let Some(Row::Run(run)) = selected.map(|row| &rows[row]) {
+            // go through the subcommand like the menu does, so the behavior is the same
+            status = match Command::new(&bin).args(options.forwarded_args()).arg(subcmd).arg(&run.id).stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).status() {
+                Ok(exit_status) if exit_status.success() => format!("{} {}: done", subcmd, run.id),
+                Ok(exit_status) => format!("{} {}: exited with {}", subcmd, run.id, exit_status),
+                Err(e) => format!("{} {}: {}", subcmd, run.id, e)
+            };
+            rows = load(options, &config, &client, &raw_client)?;
+        }
+    }
+    Ok(())
+}
@@ -2,12 +2,44 @@ use {
     std::{
         convert::Infallible,
         fmt,
+        fs::{
+            self,
+            OpenOptions
+        },
+        io,
+        path::PathBuf,
         process::Command,
-        time::Duration
+        thread,
+        time::{
+            Duration,
+            SystemTime
+        }
+    },
+    chrono::prelude::*,
+    serde::{
+        Deserialize,
+        Serialize
     },
+    url::Url,
     crate::Error
 };
 
+/// The source of the current time, which can be fixed to preview the menu at a different time.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Clock {
+    System,
+    Fixed(DateTime<Utc>)
+}
+
+impl Clock {
+    pub(crate) fn now(&self) -> DateTime<Utc> {
+        match self {
+            Clock::System => Utc::now(),
+            Clock::Fixed(now) => *now
+        }
+    }
+}
+
 pub(crate) trait CommandStatusExt {
     type Ok;
 
@@ -22,11 +54,52 @@ impl CommandStatusExt for Command {
         if status.success() {
             Ok(())
         } else {
-            Err(Error::CommandExit(name, status))
+            Err(Error::CommandExit(name, PathBuf::from(self.get_program()), status))
         }
     }
 }
 
+/// A file whose existence marks that an operation is in progress. It's removed when this is dropped.
+pub(crate) struct LockFile(PathBuf);
+
+impl LockFile {
+    /// Creates the lock file, or returns `None` if it already exists.
+    ///
+    /// A lock file older than `stale_after` is assumed to be left over from a process that crashed, and is replaced.
+    pub(crate) fn try_acquire(path: PathBuf, stale_after: Duration) -> Result<Option<LockFile>, Error> {
+        if let Some(parent) = path.parent() { fs::create_dir_all(parent)?; }
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => Ok(Some(LockFile(path))),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                let stale = fs::metadata(&path).and_then(|metadata| metadata.modified()).ok()
+                    .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                    .map_or(false, |age| age >= stale_after);
+                if !stale { return Ok(None); }
+                match fs::remove_file(&path) {
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {} // another process got there first
+                    result => { result?; }
+                }
+                LockFile::try_acquire(path, stale_after)
+            }
+            Err(e) => Err(e.into())
+        }
+    }
+
+    /// Waits until the lock file can be created.
+    pub(crate) fn acquire(path: PathBuf, stale_after: Duration) -> Result<LockFile, Error> {
+        loop {
+            if let Some(lock) = LockFile::try_acquire(path.clone(), stale_after)? { return Ok(lock); }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
 pub(crate) trait Increment {
     fn incr_by(&mut self, amount: Option<usize>);
 
@@ -64,6 +137,25 @@ impl<T: fmt::Display, I: IntoIterator<Item = T>> NatJoin for I {
     }
 }
 
+/// Describes a date relative to today, e.g. “yesterday” or “3 weeks ago”.
+pub(crate) fn relative_date(date: NaiveDate, today: NaiveDate) -> String {
+    fn ago(amount: i64, unit: &str) -> String {
+        if amount == 1 { format!("a {} ago", unit) } else { format!("{} {}s ago", amount, unit) }
+    }
+
+    match (today - date).num_days() {
+        days if days < -1 => format!("in {} days", -days),
+        -1 => "tomorrow".into(),
+        0 => "today".into(),
+        1 => "yesterday".into(),
+        days @ 2..=6 => format!("{} days ago", days),
+        7..=13 => "last week".into(),
+        days @ 14..=59 => ago(days / 7, "week"),
+        days @ 60..=364 => ago((days / 30).min(11), "month"),
+        days => ago(days / 365, "year")
+    }
+}
+
 pub(crate) trait ResultNeverExt {
     type Ok;
 
@@ -81,33 +173,266 @@ impl<T> ResultNeverExt for Result<T, Infallible> {
     }
 }
 
-pub(crate) fn format_duration(duration: Duration) -> String {
+/// How durations, such as run times, are displayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum DurationStyle {
+    /// `1h 02m 03.456s`
+    Units,
+    /// `1:02:03.456`, like a speedrun timer
+    Clock,
+    /// `1h02m03.456s`, for narrow menu bars
+    Compact
+}
+
+impl Default for DurationStyle {
+    fn default() -> DurationStyle {
+        DurationStyle::Units
+    }
+}
+
+/// Formats a duration in the given style.
+///
+/// If `trim_zeros` is set, trailing zeros are removed from the fractional seconds, so e.g. `.450` becomes `.45`.
+pub(crate) fn format_duration(duration: Duration, style: DurationStyle, trim_zeros: bool) -> String {
     const ONE_HOUR: Duration = Duration::from_secs(3600);
     const ONE_MINUTE: Duration = Duration::from_secs(60);
 
-    if duration == Duration::default() {
-        return "0s".into();
-    }
-    let mut result = if duration >= ONE_HOUR {
-        let hours = duration.as_secs() / 3600;
-        let minutes = (duration.as_secs() % 3600) / 60;
-        let seconds = duration.as_secs() % 60;
-        format!("{}h {:02}m {:02}", hours, minutes, seconds)
-    } else if duration >= ONE_MINUTE {
-        let minutes = duration.as_secs() / 60;
-        let seconds = duration.as_secs() % 60;
-        format!("{}m {:02}", minutes, seconds)
+    let hours = duration.as_secs() / 3600;
+    let minutes = (duration.as_secs() % 3600) / 60;
+    let seconds = duration.as_secs() % 60;
+    let mut fraction = if duration.subsec_nanos() == 0 {
+        String::default()
+    } else if duration.subsec_nanos() % 1_000_000 == 0 {
+        format!(".{:03}", duration.subsec_millis())
+    } else if duration.subsec_nanos() % 1_000 == 0 {
+        format!(".{:06}", duration.subsec_micros())
     } else {
-        duration.as_secs().to_string()
+        format!(".{:09}", duration.subsec_nanos())
     };
-    if duration.subsec_nanos() > 0 {
-        if duration.subsec_nanos() % 1_000_000 == 0 {
-            result += &format!(".{:03}", duration.subsec_millis());
-        } else if duration.subsec_nanos() % 1_000 == 0 {
-            result += &format!(".{:06}", duration.subsec_micros());
+    if trim_zeros {
+        let trimmed_len = fraction.trim_end_matches('0').len();
+        fraction.truncate(trimmed_len);
+    }
+    match style {
+        DurationStyle::Units | DurationStyle::Compact => {
+            if duration == Duration::default() {
+                return "0s".into();
+            }
+            let sep = if style == DurationStyle::Compact { "" } else { " " };
+            let result = if duration >= ONE_HOUR {
+                format!("{}h{}{:02}m{}{:02}", hours, sep, minutes, sep, seconds)
+            } else if duration >= ONE_MINUTE {
+                format!("{}m{}{:02}", minutes, sep, seconds)
+            } else {
+                seconds.to_string()
+            };
+            result + &fraction + "s"
+        }
+        DurationStyle::Clock => if duration >= ONE_HOUR {
+            format!("{}:{:02}:{:02}{}", hours, minutes, seconds, fraction)
+        } else {
+            format!("{}:{:02}{}", minutes, seconds, fraction)
+        }
+    }
+}
+
+/// Picks items, in order, whose durations add up to at most the given budget, skipping any that don't fit.
+///
+/// Returns the indices of the picked items.
+pub(crate) fn fill_budget(durations: impl IntoIterator<Item = Duration>, budget: Duration) -> Vec<usize> {
+    let mut remaining = budget;
+    durations.into_iter()
+        .enumerate()
+        .filter_map(|(i, duration)| if duration <= remaining {
+            remaining -= duration;
+            Some(i)
         } else {
-            result += &format!(".{:09}", duration.subsec_nanos());
+            None
+        })
+        .collect()
+}
+
+/// Escapes text for use in XML or HTML content and attribute values.
+pub(crate) fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Parses a video timestamp like `123`, `123s`, or `1h2m3s`.
+fn parse_timestamp(timestamp: &str) -> Option<Duration> {
+    if let Ok(secs) = timestamp.parse() {
+        return Some(Duration::from_secs(secs));
+    }
+    let mut secs = 0;
+    let mut digits = String::default();
+    for c in timestamp.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+        } else {
+            let amount = digits.parse::<u64>().ok()?;
+            digits.clear();
+            secs += amount * match c {
+                'h' => 3600,
+                'm' => 60,
+                's' => 1,
+                _ => return None
+            };
+        }
+    }
+    if digits.is_empty() && !timestamp.is_empty() { Some(Duration::from_secs(secs)) } else { None }
+}
+
+/// The offset at which a Twitch or YouTube video link starts playback, if any.
+pub(crate) fn start_offset(url: &Url) -> Option<Duration> {
+    let host = url.host_str()?;
+    let host = host.strip_prefix("www.").or_else(|| host.strip_prefix("m.")).unwrap_or(host);
+    let (params, fragment): (&[&str], _) = match host {
+        "youtube.com" | "youtu.be" => (&["t", "start"], url.fragment().and_then(|fragment| fragment.strip_prefix("t="))),
+        "twitch.tv" => (&["t"], None),
+        _ => return None
+    };
+    url.query_pairs()
+        .find(|(key, _)| params.contains(&&key[..]))
+        .and_then(|(_, value)| parse_timestamp(&value))
+        .or_else(|| fragment.and_then(parse_timestamp))
+        .filter(|&offset| offset > Duration::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        std::time::Duration,
+        proptest::prelude::*,
+        super::*
+    };
+
+    /// Parses the output of `format_duration` back into a duration.
+    fn parse_duration(s: &str, style: DurationStyle) -> Duration {
+        fn parse_seconds(s: &str) -> Duration {
+            let mut parts = s.splitn(2, '.');
+            let secs = parts.next().unwrap().parse().unwrap();
+            let nanos = parts.next().map_or(0, |fraction| format!("{:0<9}", fraction).parse().unwrap());
+            Duration::new(secs, nanos)
+        }
+
+        match style {
+            DurationStyle::Units | DurationStyle::Compact => {
+                let mut rest = s.strip_suffix('s').unwrap();
+                let mut duration = Duration::default();
+                if let Some((hours, tail)) = rest.split_once('h') {
+                    duration += Duration::from_secs(hours.parse::<u64>().unwrap() * 3600);
+                    rest = tail.trim_start();
+                }
+                if let Some((minutes, tail)) = rest.split_once('m') {
+                    duration += Duration::from_secs(minutes.parse::<u64>().unwrap() * 60);
+                    rest = tail.trim_start();
+                }
+                duration + parse_seconds(rest)
+            }
+            DurationStyle::Clock => {
+                let parts = s.split(':').collect::<Vec<_>>();
+                let (hours, minutes, seconds) = match parts[..] {
+                    [hours, minutes, seconds] => (hours.parse::<u64>().unwrap(), minutes.parse::<u64>().unwrap(), seconds),
+                    [minutes, seconds] => (0, minutes.parse::<u64>().unwrap(), seconds),
+                    _ => panic!("unexpected clock format: {:?}", s)
+                };
+                Duration::from_secs(hours * 3600 + minutes * 60) + parse_seconds(seconds)
+            }
+        }
+    }
+
+    fn any_style() -> impl Strategy<Value = DurationStyle> {
+        prop_oneof![Just(DurationStyle::Units), Just(DurationStyle::Clock), Just(DurationStyle::Compact)]
+    }
+
+    fn any_duration() -> impl Strategy<Value = Duration> {
+        // mostly realistic run times, with every sub-second precision
+        (0..1_000 * 3600_u64, prop_oneof![Just(0), (0..1_000_u32).prop_map(|millis| millis * 1_000_000), (0..1_000_000_u32).prop_map(|micros| micros * 1_000), 0..1_000_000_000_u32])
+            .prop_map(|(secs, nanos)| Duration::new(secs, nanos))
+    }
+
+    #[test]
+    fn examples() {
+        let duration = Duration::from_millis(3_723_450);
+        assert_eq!(format_duration(duration, DurationStyle::Units, false), "1h 02m 03.450s");
+        assert_eq!(format_duration(duration, DurationStyle::Clock, false), "1:02:03.450");
+        assert_eq!(format_duration(duration, DurationStyle::Compact, false), "1h02m03.450s");
+        assert_eq!(format_duration(duration, DurationStyle::Clock, true), "1:02:03.45");
+        assert_eq!(format_duration(Duration::from_secs(5), DurationStyle::Clock, false), "0:05");
+        assert_eq!(format_duration(Duration::default(), DurationStyle::Units, false), "0s");
+    }
+
+    #[test]
+    fn relative_dates() {
+        let today = "2026-10-18".parse::<NaiveDate>().unwrap();
+        let ago = |days| relative_date(today - chrono::Duration::days(days), today);
+        assert_eq!(ago(-2), "in 2 days");
+        assert_eq!(ago(-1), "tomorrow");
+        assert_eq!(ago(0), "today");
+        assert_eq!(ago(1), "yesterday");
+        assert_eq!(ago(2), "2 days ago");
+        assert_eq!(ago(6), "6 days ago");
+        assert_eq!(ago(7), "last week");
+        assert_eq!(ago(13), "last week");
+        assert_eq!(ago(14), "2 weeks ago");
+        assert_eq!(ago(59), "8 weeks ago");
+        assert_eq!(ago(60), "2 months ago");
+        assert_eq!(ago(364), "11 months ago");
+        assert_eq!(ago(365), "a year ago");
+        assert_eq!(ago(700), "a year ago");
+        assert_eq!(ago(730), "2 years ago");
+    }
+
+    #[test]
+    fn fill_budget_skips_runs_that_dont_fit() {
+        let durations = [25, 10, 3, 1].iter().map(|&minutes| Duration::from_secs(minutes * 60));
+        assert_eq!(fill_budget(durations, Duration::from_secs(15 * 60)), vec![1, 2, 3]);
+        assert!(fill_budget(vec![Duration::from_secs(60)], Duration::from_secs(59)).is_empty());
+    }
+
+    #[test]
+    fn start_offsets() {
+        let offset = |url| start_offset(&Url::parse(url).unwrap());
+        assert_eq!(offset("https://www.twitch.tv/videos/123456789?t=1h2m3s"), Some(Duration::from_secs(3723)));
+        assert_eq!(offset("https://www.twitch.tv/videos/123456789?t=02h00m00s"), Some(Duration::from_secs(7200)));
+        assert_eq!(offset("https://m.twitch.tv/videos/123456789?filter=all&t=45m"), Some(Duration::from_secs(2700)));
+        assert_eq!(offset("https://www.youtube.com/watch?v=aaaaaaaaaaa&t=123"), Some(Duration::from_secs(123)));
+        assert_eq!(offset("https://www.youtube.com/watch?v=aaaaaaaaaaa&t=123s"), Some(Duration::from_secs(123)));
+        assert_eq!(offset("https://youtu.be/aaaaaaaaaaa?t=1m5s"), Some(Duration::from_secs(65)));
+        assert_eq!(offset("https://www.youtube.com/embed/aaaaaaaaaaa?start=90"), Some(Duration::from_secs(90)));
+        assert_eq!(offset("https://www.youtube.com/watch?v=aaaaaaaaaaa#t=2m"), Some(Duration::from_secs(120)));
+        assert_eq!(offset("https://www.youtube.com/watch?v=aaaaaaaaaaa"), None);
+        assert_eq!(offset("https://www.youtube.com/watch?v=aaaaaaaaaaa&t=0"), None);
+        assert_eq!(offset("https://www.youtube.com/watch?v=aaaaaaaaaaa&t=soon"), None);
+        assert_eq!(offset("https://www.youtube.com/watch?v=aaaaaaaaaaa&t=5x"), None);
+        assert_eq!(offset("https://example.com/video.mp4?t=123"), None);
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip(duration in any_duration(), style in any_style(), trim_zeros in any::<bool>()) {
+            prop_assert_eq!(parse_duration(&format_duration(duration, style, trim_zeros), style), duration);
+        }
+
+        #[test]
+        fn trimmed_has_no_trailing_zeros(duration in any_duration(), style in any_style()) {
+            let formatted = format_duration(duration, style, true);
+            if let Some(fraction) = formatted.trim_end_matches('s').split('.').nth(1) {
+                prop_assert!(!fraction.ends_with('0'));
+            }
+        }
+
+        #[test]
+        fn untrimmed_fraction_is_grouped_by_three(duration in any_duration(), style in any_style()) {
+            let formatted = format_duration(duration, style, false);
+            if let Some(fraction) = formatted.trim_end_matches('s').split('.').nth(1) {
+                prop_assert!([3, 6, 9].contains(&fraction.len()));
+            }
+        }
+
+        #[test]
+        fn only_units_style_has_spaces(duration in any_duration(), style in any_style()) {
+            prop_assert_eq!(format_duration(duration, style, false).contains(' '), style == DurationStyle::Units && duration >= Duration::from_secs(60));
         }
     }
-    result + "s"
 }
@@ -0,0 +1,286 @@
+//! Shared setup for tests that run the plugin binary against a mock speedrun.com API.
+
+#![allow(unused)] // not every test uses every helper
+
+use {
+    std::{
+        fs::{
+            self,
+            File
+        },
+        io::{
+            BufReader,
+            prelude::*
+        },
+        net::TcpStream,
+        path::{
+            Path,
+            PathBuf
+        },
+        process::{
+            Child,
+            Command,
+            Output,
+            Stdio
+        },
+        sync::{
+            Arc,
+            Mutex
+        },
+        thread,
+        time::Duration
+    },
+    serde_json::{
+        Value as Json,
+        json
+    },
+    tempfile::TempDir,
+    tiny_http::{
+        Header,
+        Response,
+        Server
+    }
+};
+
+/// A local HTTP server that serves the recorded API responses in `tests/fixtures/api`.
+///
+/// A request for `/api/v1/some/path?query` is answered with `tests/fixtures/api/some/path.json`, with every occurrence of `{{base}}` replaced with the mock API's base URL. Requests for `.png` files, like cover art, are answered with the file as is. Requests with no matching fixture get a 404 response.
+pub struct MockApi {
+    pub base_url: String,
+    requests: Arc<Mutex<Vec<String>>>
+}
+
+impl MockApi {
+    pub fn start() -> MockApi {
+        let server = Server::http("127.0.0.1:0").expect("failed to start mock API server");
+        let base_url = format!("http://{}/api/v1", server.server_addr());
+        let requests = Arc::default();
+        {
+            let base_url = base_url.clone();
+            let requests = Arc::clone(&requests);
+            thread::spawn(move || for request in server.incoming_requests() {
+                let url = request.url().to_owned();
+                requests.lock().unwrap().push(url.clone());
+                let path = url.splitn(2, '?').next().unwrap().trim_start_matches("/api/v1/");
+                let fixtures_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/api");
+                if path.ends_with(".png") {
+                    if let Ok(body) = fs::read(fixtures_dir.join(path)) {
+                        let _ = request.respond(Response::from_data(body).with_header("Content-Type: image/png".parse::<Header>().unwrap()));
+                        continue;
+                    }
+                }
+                let _ = match fs::read_to_string(fixtures_dir.join(format!("{}.json", path))) {
+                    Ok(body) => request.respond(Response::from_string(body.replace("{{base}}", &base_url))
+                        .with_header("Content-Type: application/json".parse::<Header>().unwrap())
+                    ),
+                    Err(_) => request.respond(Response::from_string(r#"{"status":404,"message":"The requested resource could not be found."}"#)
+                        .with_status_code(404)
+                        .with_header("Content-Type: application/json".parse::<Header>().unwrap())
+                    )
+                };
+            });
+        }
+        MockApi { base_url, requests }
+    }
+
+    /// The request URLs received so far, without the base URL.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// An isolated plugin environment with its own config, data, and cache files.
+pub struct Env {
+    pub api: MockApi,
+    pub dir: TempDir,
+    pub api_key: Option<String>
+}
+
+impl Env {
+    /// Creates an environment following both categories of the fixture game.
+    pub fn new() -> Env {
+        let dir = tempfile::tempdir().expect("failed to create temp dir");
+        fs::create_dir_all(dir.path().join("cache")).unwrap();
+        let env = Env { api: MockApi::start(), dir, api_key: None };
+        let player_path = env.write_script("player.sh", "echo \"$@\" >> \"$(dirname \"$0\")/player.log\"");
+        env.write_config(json!({
+            "player": player_path,
+            "games": {
+                "Test Game": {
+                    "srcGames": {
+                        "g1": []
+                    },
+                    "categories": {
+                        "Any%": {
+                            "srcCategories": ["c_any"]
+                        },
+                        "100%": {
+                            "srcCategories": ["c_100"]
+                        }
+                    }
+                }
+            }
+        }));
+        env
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        self.dir.path().join("speedruncom.json")
+    }
+
+    pub fn data_path(&self) -> PathBuf {
+        self.dir.path().join("data.json")
+    }
+
+    pub fn config(&self) -> Json {
+        serde_json::from_reader(File::open(self.config_path()).unwrap()).unwrap()
+    }
+
+    pub fn write_config(&self, config: Json) {
+        serde_json::to_writer_pretty(File::create(self.config_path()).unwrap(), &config).unwrap();
+    }
+
+    pub fn write_data(&self, data: Json) {
+        serde_json::to_writer_pretty(File::create(self.data_path()).unwrap(), &data).unwrap();
+    }
+
+    pub fn data(&self) -> Json {
+        serde_json::from_reader(File::open(self.data_path()).expect("data file was not written")).unwrap()
+    }
+
+    /// Writes an executable shell script with the given body into the temp dir and returns its path.
+    pub fn write_script(&self, name: &str, body: &str) -> PathBuf {
+        let path = self.dir.path().join(name);
+        fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        #[cfg(unix)] {
+            use std::os::unix::fs::PermissionsExt as _;
+
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        path
+    }
+
+    /// The arguments the mock player was called with, one line per call.
+    pub fn player_log(&self) -> String {
+        let mut log = String::default();
+        if let Ok(mut file) = File::open(self.dir.path().join("player.log")) {
+            file.read_to_string(&mut log).unwrap();
+        }
+        log
+    }
+
+    /// Runs the plugin binary with the given arguments, panicking if it fails.
+    pub fn run(&self, args: &[&str]) -> String {
+        let output = self.run_unchecked(args);
+        assert!(output.status.success(), "plugin exited with {}\nstdout:\n{}\nstderr:\n{}", output.status, String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).expect("plugin output is not valid UTF-8")
+    }
+
+    pub fn run_unchecked(&self, args: &[&str]) -> Output {
+        self.command(args).output().expect("failed to run plugin")
+    }
+
+    /// A command running the plugin binary in this environment, for subcommands that don't exit on their own.
+    pub fn command(&self, args: &[&str]) -> Command {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_bitbar-speedruncom"));
+        cmd.args(args)
+            .env("BITBAR_SPEEDRUNCOM_CONFIG", self.config_path())
+            .env("BITBAR_SPEEDRUNCOM_DATA", self.data_path())
+            .env("BITBAR_SPEEDRUNCOM_API_URL", &self.api.base_url)
+            .env("XDG_CACHE_HOME", self.dir.path().join("cache"))
+            .env("TZ", "UTC")
+            .env_remove("BITBAR_SPEEDRUNCOM_API_KEY");
+        if let Some(ref api_key) = self.api_key {
+            cmd.env("BITBAR_SPEEDRUNCOM_API_KEY", api_key);
+        }
+        cmd
+    }
+}
+
+/// A running `daemon` subcommand which is killed when dropped.
+pub struct Daemon(Child);
+
+impl Daemon {
+    pub fn start(env: &Env) -> Daemon {
+        let daemon = Daemon(env.command(&["daemon"]).spawn().expect("failed to start daemon"));
+        let socket_path = env.dir.path().join("cache/bitbar/speedruncom.sock");
+        for _ in 0..100 {
+            if socket_path.exists() { return daemon; }
+            thread::sleep(Duration::from_millis(100));
+        }
+        panic!("daemon did not create its socket");
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// A running `serve` subcommand which is killed when dropped.
+pub struct Dashboard {
+    process: Child,
+    pub addr: String
+}
+
+impl Dashboard {
+    pub fn start(env: &Env) -> Dashboard {
+        let mut process = env.command(&["serve", "--port", "0"]).stdout(Stdio::piped()).spawn().expect("failed to start dashboard");
+        let mut line = String::default();
+        BufReader::new(process.stdout.as_mut().unwrap()).read_line(&mut line).unwrap();
+        let addr = line.trim().strip_prefix("listening on http://").unwrap_or_else(|| panic!("unexpected output: {:?}", line)).to_owned();
+        Dashboard { process, addr }
+    }
+
+    /// Sends a request and returns the response status code and body.
+    pub fn request(&self, method: &str, path: &str) -> (u16, String) {
+        self.request_with_headers(method, path, &format!("Host: {}\r\n", self.addr))
+    }
+
+    /// Sends a request with the given header lines, each ending in `\r\n`, instead of the default `Host` header.
+    pub fn request_with_headers(&self, method: &str, path: &str, headers: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(&self.addr).unwrap();
+        write!(stream, "{} {} HTTP/1.0\r\n{}Content-Length: 0\r\n\r\n", method, path, headers).unwrap();
+        let mut response = String::default();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.split(' ').nth(1).unwrap().parse().unwrap(), body.to_owned())
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+/// Compares plugin output with the golden file `tests/snapshots/<name>.txt`.
+///
+/// Values that differ between test runs, like temporary paths and the mock API's port, are replaced with placeholders first. A missing golden file fails the test, and `UPDATE_SNAPSHOTS=1` records all of them from the output instead.
+pub fn assert_snapshot(env: &Env, name: &str, output: &str) {
+    let mut normalized = output
+        .replace(env!("CARGO_BIN_EXE_bitbar-speedruncom"), "{bin}")
+        .replace(&env.api.base_url, "{api}")
+        .replace(&env.dir.path().display().to_string(), "{tmp}");
+    // the trophy icon and cover art are embedded as base64, which would make the snapshots unreadable
+    for param in &[" image=", " templateImage="] {
+        let mut offset = 0;
+        while let Some(start) = normalized[offset..].find(param).map(|idx| offset + idx + param.len()) {
+            let end = normalized[start..].find(char::is_whitespace).map_or(normalized.len(), |len| start + len);
+            normalized.replace_range(start..end, "{image}");
+            offset = start;
+        }
+    }
+    let snapshot_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots").join(format!("{}.txt", name));
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::create_dir_all(snapshot_path.parent().unwrap()).unwrap();
+        fs::write(&snapshot_path, &normalized).unwrap();
+        eprintln!("recorded snapshot {}", snapshot_path.display());
+    } else {
+        let expected = fs::read_to_string(&snapshot_path).unwrap_or_else(|e| panic!("failed to read snapshot {} (rerun with UPDATE_SNAPSHOTS=1 to record it): {}\nactual:\n{}", snapshot_path.display(), e, normalized));
+        assert!(expected == normalized, "menu differs from snapshot {} (rerun with UPDATE_SNAPSHOTS=1 to accept the change)\nexpected:\n{}\nactual:\n{}", snapshot_path.display(), expected, normalized);
+    }
+}
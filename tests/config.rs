@@ -0,0 +1,139 @@
+//! Tests for loading the config file in its different formats and converting between them.
+
+mod common;
+
+use {
+    std::{
+        fs,
+        path::Path
+    },
+    serde_json::{
+        Value as Json,
+        json
+    },
+    crate::common::Env
+};
+
+/// Writes the given config as JSON, TOML, or YAML, depending on the path's extension.
+fn write_config_as(path: &Path, config: &Json) {
+    let text = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::to_string_pretty(config).unwrap(),
+        Some("toml") => toml::to_string_pretty(&toml::Value::try_from(config).unwrap()).unwrap(),
+        Some("yaml") | Some("yml") => serde_yaml::to_string(config).unwrap(),
+        _ => panic!("unknown config format: {}", path.display())
+    };
+    fs::write(path, text).unwrap();
+}
+
+fn read_json(path: &Path) -> Json {
+    serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[test]
+fn toml_and_yaml_configs_are_loaded() {
+    let env = Env::new();
+    for ext in &["toml", "yaml", "yml"] {
+        let config_path = env.dir.path().join(format!("speedruncom.{}", ext));
+        write_config_as(&config_path, &env.config());
+        let output = env.command(&[]).env("BITBAR_SPEEDRUNCOM_CONFIG", &config_path).output().unwrap();
+        assert!(output.status.success(), "plugin failed with a .{} config:\n{}", ext, String::from_utf8_lossy(&output.stderr));
+        let menu = String::from_utf8(output.stdout).unwrap();
+        assert!(menu.contains("New WR in Any%: 25m 00.123s"), "unexpected menu with a .{} config:\n{}", ext, menu);
+    }
+}
+
+#[test]
+fn config_formats_take_precedence_in_order() {
+    let env = Env::new();
+    let xdg_config_home = env.dir.path().join("xdg");
+    let plugins_dir = xdg_config_home.join("bitbar/plugins");
+    fs::create_dir_all(&plugins_dir).unwrap();
+    for ext in &["toml", "yaml", "yml", "json"] {
+        write_config_as(&plugins_dir.join(format!("speedruncom.{}", ext)), &json!({"apiKey": ext}));
+    }
+    let found_config = || {
+        let output_path = env.dir.path().join("found.json");
+        let output = env.command(&["config", "convert"]).arg(&output_path)
+            .env_remove("BITBAR_SPEEDRUNCOM_CONFIG")
+            .env("XDG_CONFIG_HOME", &xdg_config_home)
+            .env("XDG_CONFIG_DIRS", env.dir.path().join("no-such-dir"))
+            .output().unwrap();
+        assert!(output.status.success(), "config convert failed:\n{}", String::from_utf8_lossy(&output.stderr));
+        read_json(&output_path)["apiKey"].clone()
+    };
+    assert_eq!(found_config(), json!("toml"));
+    fs::remove_file(plugins_dir.join("speedruncom.toml")).unwrap();
+    assert_eq!(found_config(), json!("yaml"));
+    fs::remove_file(plugins_dir.join("speedruncom.yaml")).unwrap();
+    assert_eq!(found_config(), json!("yml"));
+    fs::remove_file(plugins_dir.join("speedruncom.yml")).unwrap();
+    assert_eq!(found_config(), json!("json"));
+}
+
+#[test]
+fn converted_configs_omit_defaults() {
+    let env = Env::new();
+    let output_path = env.dir.path().join("converted.json");
+    env.run(&["config", "convert", output_path.to_str().unwrap()]);
+    assert_eq!(read_json(&output_path), env.config());
+}
+
+#[test]
+fn converted_configs_round_trip() {
+    let env = Env::new();
+    let mut config = env.config();
+    config["coverArt"] = json!(false);
+    config["fillMinutes"] = json!([45]);
+    config["handlers"] = json!({"twitch.tv": "browser"});
+    config["linkCheck"] = json!("head");
+    config["quietHours"] = json!({"start": "22:00", "end": "07:00"});
+    config["sortBy"] = json!("priority");
+    config["games"]["Test Game"]["priority"] = json!(2);
+    env.write_config(config.clone());
+    let mut input = env.config_path();
+    for ext in &["toml", "yaml", "json"] {
+        let output = env.dir.path().join(format!("converted.{}", ext));
+        env.run(&["config", "convert", input.to_str().unwrap(), output.to_str().unwrap()]);
+        input = output;
+    }
+    assert_eq!(read_json(&input), config);
+}
+
+/// Moves the games from the default config into the given include files, relative to the config file, and includes them using the given patterns.
+fn move_games_to_includes(env: &Env, includes: &[&str], patterns: &[&str]) {
+    let mut config = env.config();
+    let games = config["games"].take();
+    for include in includes {
+        let include_path = env.dir.path().join(include);
+        fs::create_dir_all(include_path.parent().unwrap()).unwrap();
+        write_config_as(&include_path, &games);
+    }
+    config.as_object_mut().unwrap().remove("games");
+    config["include"] = json!(patterns);
+    env.write_config(config);
+}
+
+#[test]
+fn games_are_included_by_path() {
+    let env = Env::new();
+    move_games_to_includes(&env, &["games/test.yaml"], &["games/test.yaml"]);
+    let menu = env.run(&[]);
+    assert!(menu.contains("New WR in Any%: 25m 00.123s"), "included game missing from menu:\n{}", menu);
+}
+
+#[test]
+fn games_are_included_by_glob() {
+    let env = Env::new();
+    move_games_to_includes(&env, &["games/test.toml"], &["games/*.toml"]);
+    let menu = env.run(&[]);
+    assert!(menu.contains("New WR in Any%: 25m 00.123s"), "included game missing from menu:\n{}", menu);
+}
+
+#[test]
+fn games_included_twice_are_reported() {
+    let env = Env::new();
+    move_games_to_includes(&env, &["games/a.json", "games/b.json"], &["games/*.json"]);
+    let menu = env.run(&[]);
+    assert!(menu.contains("is already configured"), "missing conflict error in menu:\n{}", menu);
+    assert!(menu.contains("Open Included File"));
+}
@@ -0,0 +1,98 @@
+//! Tests for the `daemon` subcommand, which serves the menu and actions over a Unix socket.
+
+mod common;
+
+use {
+    std::{
+        fs,
+        io::{
+            BufReader,
+            prelude::*
+        },
+        os::unix::net::UnixStream,
+        time::{
+            Duration,
+            Instant
+        }
+    },
+    serde_json::json,
+    crate::common::{
+        Daemon,
+        Env
+    }
+};
+
+#[test]
+fn daemon_serves_menu_from_memory() {
+    let env = Env::new();
+    let _daemon = Daemon::start(&env);
+    let requests = env.api.requests().len();
+    let menu = env.run(&[]);
+    assert!(menu.starts_with("2 |"), "unexpected title in menu:\n{}", menu);
+    assert_eq!(env.api.requests().len(), requests);
+}
+
+#[test]
+fn daemon_handles_actions() {
+    let env = Env::new();
+    let _daemon = Daemon::start(&env);
+    env.run(&["check", "r2"]);
+    assert_eq!(env.data()["runs"]["r2"]["watched"], json!(true));
+    env.run(&["unwatchable", "r1"]);
+    let menu = env.run(&[]);
+    assert!(menu.starts_with("1 |"), "unexpected title in menu:\n{}", menu);
+    assert!(menu.contains("New WR in Any%: 25m 12.500s"));
+}
+
+#[test]
+fn daemon_notices_data_changed_by_other_processes() {
+    let env = Env::new();
+    let _daemon = Daemon::start(&env);
+    assert!(env.run(&[]).starts_with("2 |"));
+    env.write_data(json!({
+        "runs": {
+            "r1": {
+                "unwatchable": true
+            }
+        }
+    }));
+    let menu = env.run(&[]);
+    assert!(menu.contains("New WR in Any%: 25m 12.500s"), "stale WR in menu:\n{}", menu);
+}
+
+#[test]
+fn idle_connection_does_not_block_daemon() {
+    let env = Env::new();
+    let _daemon = Daemon::start(&env);
+    let _idle = UnixStream::connect(env.dir.path().join("cache/bitbar/speedruncom.sock")).unwrap();
+    let start = Instant::now();
+    assert!(env.run(&[]).starts_with("2 |"));
+    assert!(start.elapsed() < Duration::from_secs(30));
+}
+
+#[test]
+fn daemon_rotates_its_log() {
+    let env = Env::new();
+    let _daemon = Daemon::start(&env);
+    let log_dir = env.dir.path().join("cache/bitbar");
+    // grow the log beyond its maximum size without starting another process, which would rotate it on startup
+    fs::OpenOptions::new().append(true).open(log_dir.join("speedruncom.log")).unwrap().write_all(&vec![b'x'; 2 * 1024 * 1024]).unwrap();
+    let mut stream = UnixStream::connect(log_dir.join("speedruncom.sock")).unwrap();
+    serde_json::to_writer(&stream, &json!({
+        "configPath": env.config_path(),
+        "dataPath": env.data_path(),
+        "args": []
+    })).unwrap();
+    stream.write_all(b"\n").unwrap();
+    let mut response = String::default();
+    BufReader::new(&stream).read_line(&mut response).unwrap();
+    assert!(log_dir.join("speedruncom.log.1").exists());
+    assert!(fs::metadata(log_dir.join("speedruncom.log")).unwrap().len() < 1024 * 1024);
+}
+
+#[test]
+fn second_daemon_is_refused() {
+    let env = Env::new();
+    let _daemon = Daemon::start(&env);
+    assert!(!env.run_unchecked(&["daemon"]).status.success());
+}
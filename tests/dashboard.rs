@@ -0,0 +1,69 @@
+//! Tests for the HTTP dashboard started by the `serve` subcommand.
+
+mod common;
+
+use {
+    serde_json::json,
+    crate::common::{
+        Dashboard,
+        Env
+    }
+};
+
+#[test]
+fn dashboard_lists_pending_runs() {
+    let env = Env::new();
+    let dashboard = Dashboard::start(&env);
+    let (status, html) = dashboard.request("GET", "/");
+    assert_eq!(status, 200);
+    assert!(html.contains("<h2>Test Game</h2>"), "unexpected dashboard:\n{}", html);
+    assert!(html.contains(r#"<a href="https://www.youtube.com/watch?v=aaaaaaaaaaa">Watch</a>"#));
+    assert!(html.contains(r#"action="/runs/r1/check""#));
+    let (status, body) = dashboard.request("GET", "/api/pending");
+    assert_eq!(status, 200);
+    let pending = serde_json::from_str::<serde_json::Value>(&body).unwrap();
+    assert_eq!(pending[0]["game"], json!("Test Game"));
+    assert_eq!(pending[0]["runs"][0]["id"], json!("r1"));
+    assert_eq!(pending[0]["runs"][0]["runners"], json!(["Runner One"]));
+}
+
+#[test]
+fn dashboard_actions_update_data() {
+    let env = Env::new();
+    let dashboard = Dashboard::start(&env);
+    assert_eq!(dashboard.request("POST", "/api/runs/r1/check").0, 200);
+    assert_eq!(env.data()["runs"]["r1"]["watched"], json!(true));
+    assert_eq!(dashboard.request("POST", "/runs/r2/unwatchable").0, 303);
+    assert_eq!(env.data()["runs"]["r2"]["unwatchable"], json!(true));
+    assert_eq!(dashboard.request("POST", "/api/runs/r2/frobnicate").0, 404);
+    let (_, body) = dashboard.request("GET", "/api/pending");
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&body).unwrap(), json!([]));
+}
+
+#[test]
+fn dashboard_only_listens_on_localhost() {
+    let env = Env::new();
+    let dashboard = Dashboard::start(&env);
+    assert!(dashboard.addr.starts_with("127.0.0.1:"));
+}
+
+#[test]
+fn dashboard_rejects_requests_from_other_sites() {
+    let env = Env::new();
+    let dashboard = Dashboard::start(&env);
+    let port = dashboard.addr.rsplit(':').next().unwrap();
+    assert_eq!(dashboard.request_with_headers("POST", "/runs/r1/check", &format!("Host: {}\r\nOrigin: http://evil.example\r\n", dashboard.addr)).0, 403);
+    assert_eq!(dashboard.request_with_headers("GET", "/api/pending", &format!("Host: evil.example:{}\r\n", port)).0, 403);
+    assert!(!env.data_path().exists());
+    assert_eq!(dashboard.request_with_headers("POST", "/runs/r1/check", &format!("Host: localhost:{}\r\nOrigin: http://localhost:{}\r\n", port, port)).0, 303);
+    assert_eq!(env.data()["runs"]["r1"]["watched"], json!(true));
+}
+
+#[test]
+fn unknown_serve_option_is_reported() {
+    let env = Env::new();
+    let output = env.run_unchecked(&["serve", "--prot", "0"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("unknown option: --prot"), "unexpected error:\n{}", stderr);
+}
@@ -0,0 +1,416 @@
+//! Runs the plugin binary against the mock API in `common`, so no network access is needed.
+
+mod common;
+
+use {
+    serde_json::json,
+    crate::common::Env
+};
+
+#[test]
+fn bitbar_shows_pending_wrs() {
+    let env = Env::new();
+    let menu = env.run(&[]);
+    assert!(menu.starts_with("2 |"), "unexpected title in menu:\n{}", menu);
+    assert!(menu.contains("Test Game"));
+    assert!(menu.contains("New WR in Any%: 25m 00.123s"));
+    assert!(menu.contains("New WR in 100%: 1h 02m 03s"));
+    assert!(env.api.requests().iter().any(|url| url.starts_with("/api/v1/leaderboards/g1/category/c_any")));
+}
+
+#[test]
+fn disk_cache_hits_are_logged() {
+    let env = Env::new();
+    env.run(&["--verbose"]);
+    env.run(&["--verbose"]);
+    let log = std::fs::read_to_string(env.dir.path().join("cache/bitbar/speedruncom.log")).unwrap();
+    assert!(log.contains("api_request kind=game id=g1 "), "missing request in log:\n{}", log);
+    assert!(log.contains("cache_hit kind=game id=g1 source=disk "), "missing disk cache hit in log:\n{}", log);
+}
+
+#[test]
+fn bitbar_shows_unread_notifications() {
+    let mut env = Env::new();
+    env.api_key = Some("mock-api-key".into());
+    let menu = env.run(&[]);
+    assert!(menu.starts_with("3 |"), "unexpected title in menu:\n{}", menu);
+    assert!(menu.contains("Your run of Test Game Any% has been verified."));
+    assert!(!menu.contains("Runner Two followed you."));
+}
+
+#[test]
+fn unknown_subcommand_is_reported() {
+    let env = Env::new();
+    let output = env.run_unchecked(&["frobnicate"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("error parsing command line: unknown subcommand: frobnicate"), "unexpected error:\n{}", stderr);
+}
+
+#[test]
+fn check_marks_run_as_watched() {
+    let env = Env::new();
+    env.run(&["check", "r1"]);
+    assert_eq!(env.data()["runs"]["r1"]["watched"], json!(true));
+    let menu = env.run(&[]);
+    assert!(menu.starts_with("1 |"), "unexpected title in menu:\n{}", menu);
+    assert!(!menu.contains("New WR in Any%"));
+}
+
+#[test]
+fn defer_hides_run_until_deferral_expires() {
+    let env = Env::new();
+    env.run(&["defer", "r2"]);
+    assert!(env.data()["runs"]["r2"]["deferred"].is_string());
+    assert!(!env.run(&[]).contains("New WR in 100%"));
+    env.write_data(json!({
+        "runs": {
+            "r2": {
+                "deferred": "2000-01-01T00:00:00Z"
+            }
+        }
+    }));
+    assert!(env.run(&[]).contains("New WR in 100%"));
+}
+
+#[test]
+fn unwatchable_falls_back_to_next_run() {
+    let env = Env::new();
+    env.run(&["unwatchable", "r1"]);
+    assert_eq!(env.data()["runs"]["r1"]["unwatchable"], json!(true));
+    assert!(env.run(&[]).contains("New WR in Any%: 25m 12.500s"));
+}
+
+#[test]
+fn watch_opens_videos_and_marks_run_as_watched() {
+    let env = Env::new();
+    env.run(&["watch", "r1"]);
+    assert!(env.player_log().contains("https://www.youtube.com/watch?v=aaaaaaaaaaa"));
+    assert_eq!(env.data()["runs"]["r1"]["watched"], json!(true));
+}
+
+#[test]
+fn changes_during_playback_are_kept() {
+    let env = Env::new();
+    let player_path = env.write_script("defer-player.sh", &format!("\"{}\" defer r2", env!("CARGO_BIN_EXE_bitbar-speedruncom")));
+    let mut config = env.config();
+    config["player"] = json!(player_path);
+    env.write_config(config);
+    env.run(&["watch", "r1"]);
+    assert_eq!(env.data()["runs"]["r1"]["watched"], json!(true));
+    assert!(env.data()["runs"]["r2"]["deferred"].is_string());
+}
+
+#[test]
+fn menu_commands_use_the_same_profile() {
+    let env = Env::new();
+    let menu = env.run(&[]);
+    assert!(menu.contains(&format!("--config={}", env.config_path().display())));
+    assert!(menu.contains(&format!("--data={}", env.data_path().display())));
+}
+
+#[test]
+fn relative_paths_are_forwarded_as_absolute_paths() {
+    let env = Env::new();
+    let output = env.command(&["--config", "speedruncom.json", "--data", "data.json"])
+        .env_remove("BITBAR_SPEEDRUNCOM_CONFIG")
+        .env_remove("BITBAR_SPEEDRUNCOM_DATA")
+        .current_dir(env.dir.path())
+        .output().unwrap();
+    assert!(output.status.success(), "plugin failed:\n{}", String::from_utf8_lossy(&output.stderr));
+    let menu = String::from_utf8(output.stdout).unwrap();
+    let dir = env.dir.path().canonicalize().unwrap(); // the working directory as seen by the plugin
+    assert!(menu.contains(&format!("--config={}", dir.join("speedruncom.json").display())), "relative config path in menu:\n{}", menu);
+    assert!(menu.contains(&format!("--data={}", dir.join("data.json").display())), "relative data path in menu:\n{}", menu);
+}
+
+#[test]
+fn fixed_clock_controls_deferral() {
+    let env = Env::new();
+    env.run(&["--now", "2030-01-01T00:00:00Z", "defer", "r2"]);
+    assert_eq!(env.data()["runs"]["r2"]["deferred"], json!("2030-01-02T00:00:00Z"));
+    assert!(!env.run(&["--now", "2030-01-01T23:59:59Z"]).contains("New WR in 100%"));
+    assert!(env.run(&["--now", "2030-01-02T00:00:01Z"]).contains("New WR in 100%"));
+}
+
+#[test]
+fn fixed_clock_controls_timespecs() {
+    let env = Env::new();
+    env.run(&["--now", "2030-01-01T00:00:00Z", "defer", "r2", "r:7d"]);
+    let deferred = env.data()["runs"]["r2"]["deferred"].as_str().unwrap().to_owned();
+    assert!(deferred.starts_with("2030-01-08T00:00:00"), "unexpected deferral: {}", deferred);
+    assert!(!env.run(&["--now", "2030-01-07T23:59:59Z"]).contains("New WR in 100%"));
+    assert!(env.run(&["--now", "2030-01-08T00:00:01Z"]).contains("New WR in 100%"));
+}
+
+#[test]
+fn runners_link_to_profiles() {
+    let env = Env::new();
+    let menu = env.run(&[]);
+    assert!(menu.contains("New WR in Any%: 25m 00.123s by Runner One"));
+    assert!(menu.contains("Runner: \u{1f1e8}\u{1f1e6} Runner One (they/them)"));
+    assert!(menu.contains("https://www.speedrun.com/user/RunnerOne"));
+    assert!(menu.contains("Runner: \u{1f1e9}\u{1f1ea} Runner Two"));
+}
+
+#[test]
+fn snoozed_games_and_categories_are_hidden() {
+    let env = Env::new();
+    env.run(&["--now", "2030-01-01T00:00:00Z", "snooze-category", "Test Game", "100%"]);
+    assert_eq!(env.data()["snoozedCategories"]["Test Game"]["100%"], json!("2030-01-02T00:00:00Z"));
+    let menu = env.run(&["--now", "2030-01-01T12:00:00Z"]);
+    assert!(menu.starts_with("1 |"), "unexpected title in menu:\n{}", menu);
+    assert!(!menu.contains("New WR in 100%"));
+    env.run(&["--now", "2030-01-01T00:00:00Z", "snooze-game", "Test Game"]);
+    assert!(env.run(&["--now", "2030-01-01T12:00:00Z"]).is_empty());
+    assert!(env.run(&["--now", "2030-01-02T12:00:00Z"]).starts_with("2 |"));
+}
+
+#[test]
+fn focus_mode_hides_count() {
+    let env = Env::new();
+    env.run(&["--now", "2030-01-01T00:00:00Z", "focus"]);
+    assert_eq!(env.data()["focusUntil"], json!("2030-01-01T01:00:00Z"));
+    let menu = env.run(&["--now", "2030-01-01T00:30:00Z"]);
+    assert!(menu.starts_with(" |"), "unexpected title in menu:\n{}", menu);
+    assert!(menu.contains("New WR in Any%"));
+    assert!(menu.contains("End Focus Mode"));
+    env.run(&["focus", "off"]);
+    assert!(env.run(&["--now", "2030-01-01T00:30:00Z"]).starts_with("2 |"));
+}
+
+#[test]
+fn quiet_hours_hide_count() {
+    let env = Env::new();
+    let mut config = env.config();
+    config["quietHours"] = json!({
+        "start": "22:00",
+        "end": "07:00"
+    });
+    env.write_config(config);
+    assert!(env.run(&["--now", "2030-01-01T23:00:00Z"]).starts_with(" |"));
+    assert!(env.run(&["--now", "2030-01-01T06:59:00Z"]).starts_with(" |"));
+    assert!(env.run(&["--now", "2030-01-01T12:00:00Z"]).starts_with("2 |"));
+}
+
+#[test]
+fn invalid_date_format_shows_error_menu() {
+    let env = Env::new();
+    let mut config = env.config();
+    config["dateFormat"] = json!("%Y-%m-%d %Q");
+    env.write_config(config);
+    let menu = env.run(&[]);
+    assert!(menu.starts_with("? |"), "unexpected title in menu:\n{}", menu);
+    assert!(menu.contains("invalid date format: \"%Y-%m-%d %Q\""));
+    assert!(menu.contains("Open Config File"));
+}
+
+#[test]
+fn cover_art_is_shown_and_cached() {
+    let env = Env::new();
+    let menu = env.run(&[]);
+    let header = menu.lines().find(|line| line.starts_with("Test Game (")).expect("missing game header");
+    assert!(header.contains(" image="), "missing cover art in game header:\n{}", header);
+    assert!(env.dir.path().join("cache/bitbar/speedruncom-covers/g1.png").exists());
+    env.run(&[]);
+    assert_eq!(env.api.requests().iter().filter(|url| url.ends_with("/cover-tiny.png")).count(), 1);
+}
+
+#[test]
+fn cover_art_can_be_turned_off() {
+    let env = Env::new();
+    let mut config = env.config();
+    config["coverArt"] = json!(false);
+    env.write_config(config);
+    let menu = env.run(&[]);
+    assert!(!menu.contains(" image="), "unexpected cover art in menu:\n{}", menu);
+    assert!(!env.api.requests().iter().any(|url| url.ends_with("/cover-tiny.png")));
+}
+
+#[test]
+fn watch_time_budget() {
+    let env = Env::new();
+    let menu = env.run(&[]);
+    assert!(menu.contains("Test Game (1h 27m 03.123s)"), "missing game subtotal in menu:\n{}", menu);
+    assert!(menu.contains("Total Watch Time: 1h 27m 03.123s"));
+    assert!(menu.contains("Fill 30 Minutes"));
+    assert!(menu.contains("1 run, 25m 00.123s"));
+}
+
+#[test]
+fn export_playlist_writes_pending_runs() {
+    let env = Env::new();
+    let m3u_path = env.dir.path().join("wrs.m3u");
+    env.run(&["export-playlist", m3u_path.to_str().unwrap()]);
+    let m3u = std::fs::read_to_string(&m3u_path).unwrap();
+    assert!(m3u.starts_with("#EXTM3U\n"));
+    assert!(m3u.contains("#EXTINF:1500,Test Game – Any% – 25m 00.123s by Runner One\nhttps://www.youtube.com/watch?v=aaaaaaaaaaa\n"), "unexpected playlist:\n{}", m3u);
+    assert!(m3u.contains("Test Game – 100% – 1h 02m 03s by Runner Two"));
+    let xspf_path = env.dir.path().join("wrs.xspf");
+    env.run(&["export-playlist", "--category", "100%", "--mark-watched", xspf_path.to_str().unwrap()]);
+    let xspf = std::fs::read_to_string(&xspf_path).unwrap();
+    assert!(xspf.contains("<title>Test Game – 100% – 1h 02m 03s by Runner Two</title>"), "unexpected playlist:\n{}", xspf);
+    assert!(!xspf.contains("Any%"));
+    assert_eq!(env.data()["runs"]["r2"]["watched"], json!(true));
+    assert_eq!(env.data()["runs"]["r1"]["watched"], json!(null));
+}
+
+#[test]
+fn downloaded_runs_are_watched_offline_and_cleaned_up() {
+    let env = Env::new();
+    let downloader = env.write_script("downloader.sh", r#"while [ $# -gt 0 ]; do
+    case "$1" in
+        --output) output="$2"; shift ;;
+    esac
+    shift
+done
+path="$(echo "$output" | sed 's/%(ext)s/mp4/')"
+echo video > "$path"
+echo "$path""#);
+    let library = env.dir.path().join("library");
+    let mut config = env.config();
+    config["downloader"] = json!(downloader);
+    config["library"] = json!(library);
+    config["deleteWatchedDownloadsAfter"] = json!(0);
+    env.write_config(config);
+    assert!(env.run(&[]).contains("Download for Offline"));
+    env.run(&["download", "r1"]);
+    let video_path = library.join("r1-1.mp4");
+    assert!(video_path.exists());
+    assert_eq!(env.data()["runs"]["r1"]["downloads"], json!([video_path]));
+    assert!(env.run(&[]).contains("Downloaded for Offline"));
+    env.run(&["watch", "r1"]);
+    assert!(env.player_log().contains(video_path.to_str().unwrap()));
+    assert!(!env.player_log().contains("youtube.com"));
+    env.run(&[]);
+    assert!(!video_path.exists());
+    assert_eq!(env.data()["runs"]["r1"]["downloads"], json!([]));
+}
+
+#[test]
+fn downloads_without_watch_time_are_cleaned_up() {
+    let env = Env::new();
+    let legacy_path = env.dir.path().join("r1-1.mp4");
+    let unwatchable_path = env.dir.path().join("r2-1.mp4");
+    std::fs::write(&legacy_path, "video").unwrap();
+    std::fs::write(&unwatchable_path, "video").unwrap();
+    let mut config = env.config();
+    config["deleteWatchedDownloadsAfter"] = json!(0);
+    env.write_config(config);
+    env.write_data(json!({
+        "runs": {
+            "r1": {
+                "watched": true,
+                "downloads": [legacy_path]
+            },
+            "r2": {
+                "unwatchable": true,
+                "downloads": [unwatchable_path]
+            }
+        }
+    }));
+    env.run(&[]);
+    assert!(!legacy_path.exists());
+    assert!(!unwatchable_path.exists());
+}
+
+fn write_dead_video_config(env: &Env) {
+    env.write_config(json!({
+        "player": env.dir.path().join("player.sh"),
+        "linkCheck": "head",
+        "games": {
+            "Test Game": {
+                "srcGames": {
+                    "g1": []
+                },
+                "categories": {
+                    "Dead Video": {
+                        "srcCategories": ["c_dead"]
+                    }
+                }
+            }
+        }
+    }));
+}
+
+#[test]
+fn dead_video_links_are_flagged() {
+    let env = Env::new();
+    write_dead_video_config(&env);
+    env.run(&["check-links"]);
+    assert!(env.api.requests().iter().any(|url| url == "/api/v1/videos/deleted"));
+    assert_eq!(env.data()["runs"]["r6"]["videoCheck"]["available"], json!(false));
+    let menu = env.run(&[]);
+    assert!(menu.contains("⚠ New WR in Dead Video"), "unexpected menu:\n{}", menu);
+    assert!(menu.contains("Video unavailable — mark unwatchable?"));
+    env.run(&["unwatchable", "r6"]);
+    assert!(!env.run(&[]).contains("Dead Video"));
+}
+
+#[test]
+fn only_one_link_check_runs_at_a_time() {
+    let env = Env::new();
+    write_dead_video_config(&env);
+    let lock_path = env.dir.path().join("data.json.check-links");
+    std::fs::write(&lock_path, "").unwrap();
+    env.run(&["check-links"]);
+    assert!(!env.api.requests().iter().any(|url| url == "/api/v1/videos/deleted"));
+    std::fs::remove_file(&lock_path).unwrap();
+    env.run(&["check-links"]);
+    assert!(env.api.requests().iter().any(|url| url == "/api/v1/videos/deleted"));
+    assert!(!lock_path.exists());
+}
+
+/// Checks the links with yt-dlp replaced by the given script, or by a missing program if `None`, and returns the recorded check of `r6`.
+fn check_links_with_downloader(env: &Env, script: Option<&str>) -> serde_json::Value {
+    write_dead_video_config(env);
+    let mut config = env.config();
+    config["linkCheck"] = json!("ytDlp");
+    config["downloader"] = match script {
+        Some(script) => json!(env.write_script("downloader.sh", script)),
+        None => json!(env.dir.path().join("no-such-downloader"))
+    };
+    env.write_config(config);
+    env.run(&["check-links"]);
+    env.data()["runs"]["r6"]["videoCheck"].clone()
+}
+
+#[test]
+fn downloader_reports_deleted_videos() {
+    let env = Env::new();
+    let check = check_links_with_downloader(&env, Some("echo 'ERROR: [youtube] xxxxxxxxxxx: Video unavailable' >&2; exit 1"));
+    assert_eq!(check["available"], json!(false));
+}
+
+#[test]
+fn inconclusive_link_checks_are_recorded() {
+    let env = Env::new();
+    let check = check_links_with_downloader(&env, Some("echo 'ERROR: Unable to download webpage: network is unreachable' >&2; exit 1"));
+    assert_eq!(check["available"], json!(null));
+    assert!(check["checkedAt"].is_string());
+    assert!(!env.run(&[]).contains("Video unavailable"));
+}
+
+#[test]
+fn missing_downloader_is_inconclusive() {
+    let env = Env::new();
+    let check = check_links_with_downloader(&env, None);
+    assert_eq!(check["available"], json!(null));
+}
+
+#[test]
+fn videos_are_opened_by_host() {
+    let env = Env::new();
+    let handler = env.write_script("handler.sh", "echo \"$@\" >> \"$(dirname \"$0\")/handler.log\"");
+    let mut config = env.config();
+    config["handlers"] = json!({
+        "youtube.com": handler,
+        "twitch.tv": "browser"
+    });
+    env.write_config(config);
+    let menu = env.run(&[]);
+    assert!(menu.contains("href=https://www.twitch.tv/videos/123456789"), "unexpected menu:\n{}", menu);
+    env.run(&["watch", "r1"]);
+    assert!(std::fs::read_to_string(env.dir.path().join("handler.log")).unwrap().contains("https://www.youtube.com/watch?v=aaaaaaaaaaa"));
+    assert!(env.player_log().is_empty());
+}
@@ -0,0 +1,143 @@
+//! Golden-file tests for the layout of the rendered menu.
+
+mod common;
+
+use {
+    serde_json::json,
+    crate::common::{
+        Env,
+        assert_snapshot
+    }
+};
+
+/// Relative dates in the menu are rendered as of this time.
+const NOW: &str = "2026-10-18T12:00:00Z";
+
+fn two_games_config(player: serde_json::Value) -> serde_json::Value {
+    json!({
+        "player": player,
+        "games": {
+            "Test Game": {
+                "srcGames": {
+                    "g1": []
+                },
+                "categories": {
+                    "Any%": {
+                        "srcCategories": ["c_any"]
+                    },
+                    "100%": {
+                        "srcCategories": ["c_100"]
+                    },
+                    "Glitchless": {
+                        "srcCategories": ["c_glitchless"]
+                    }
+                }
+            },
+            "Other Game": {
+                "srcGames": {
+                    "g2": []
+                },
+                "categories": {
+                    "Any%": {
+                        "srcCategories": ["c_short"]
+                    }
+                }
+            }
+        }
+    })
+}
+
+fn two_games(env: &Env, player: serde_json::Value) {
+    env.write_config(two_games_config(player));
+}
+
+#[test]
+fn sorted_by_fastest_time() {
+    let env = Env::new();
+    two_games(&env, json!(env.dir.path().join("player.sh")));
+    assert_snapshot(&env, "sorted_by_fastest_time", &env.run(&["--now", NOW]));
+}
+
+#[test]
+fn sorted_by_priority() {
+    let env = Env::new();
+    let mut config = two_games_config(json!(env.dir.path().join("player.sh")));
+    config["sortBy"] = json!("priority");
+    config["games"]["Test Game"]["priority"] = json!(1);
+    env.write_config(config);
+    assert_snapshot(&env, "sorted_by_priority", &env.run(&["--now", NOW]));
+}
+
+#[test]
+fn sorted_by_name() {
+    let env = Env::new();
+    let mut config = two_games_config(json!(env.dir.path().join("player.sh")));
+    config["sortBy"] = json!("name");
+    env.write_config(config);
+    assert_snapshot(&env, "sorted_by_name", &env.run(&["--now", NOW]));
+}
+
+#[test]
+fn overflow_submenu() {
+    let env = Env::new();
+    let mut config = two_games_config(json!(env.dir.path().join("player.sh")));
+    config["maxItems"] = json!(2);
+    env.write_config(config);
+    let output = env.run(&["--now", NOW]);
+    assert!(output.starts_with("4 |"), "unexpected title in menu:\n{}", output);
+    assert_snapshot(&env, "overflow_submenu", &output);
+}
+
+#[test]
+fn tied_with_watched_run() {
+    let env = Env::new();
+    two_games(&env, json!(env.dir.path().join("player.sh")));
+    env.write_data(json!({
+        "runs": {
+            "r3": {
+                "watched": true
+            }
+        }
+    }));
+    assert_snapshot(&env, "tied_with_watched_run", &env.run(&["--now", NOW]));
+}
+
+#[test]
+fn deferred_runs() {
+    let env = Env::new();
+    two_games(&env, json!(env.dir.path().join("player.sh")));
+    env.write_data(json!({
+        "runs": {
+            "r1": {
+                "deferred": "2999-01-01T00:00:00Z"
+            },
+            "r5": {
+                "deferred": "2000-01-01T00:00:00Z"
+            }
+        }
+    }));
+    assert_snapshot(&env, "deferred_runs", &env.run(&["--now", NOW]));
+}
+
+#[test]
+fn without_player() {
+    let env = Env::new();
+    two_games(&env, json!(env.dir.path().join("no-such-player")));
+    assert_snapshot(&env, "without_player", &env.run(&["--now", NOW]));
+}
+
+#[test]
+fn nothing_pending() {
+    let env = Env::new();
+    env.write_data(json!({
+        "runs": {
+            "r1": {
+                "watched": true
+            },
+            "r2": {
+                "deferred": "2999-01-01T00:00:00Z"
+            }
+        }
+    }));
+    assert_snapshot(&env, "nothing_pending", &env.run(&["--now", NOW]));
+}